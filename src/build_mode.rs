@@ -0,0 +1,246 @@
+use bevy::prelude::*;
+
+use crate::{
+    BuildingRegistry, BuildingRotation, InputMode, MouseCursor, key_bindings,
+    map::{FIELD_SIZE, Map},
+    terrain::TerrainRegistry,
+    toasts::ToastMessage,
+    user_controls::control_panel_hovered,
+};
+
+/// Building currently selected for placement.
+/// Only set while in [`InputMode::Build`].
+#[derive(Resource, Default)]
+pub struct CursorBuilding {
+    building_id: Option<String>,
+    /// Rotation applied to the next placed building.
+    /// Kept between build mode sessions so rows of buildings can share a facing.
+    rotation: BuildingRotation,
+}
+
+/// Requests entering [`InputMode::Build`] with the given building selected,
+/// e.g. from a hotkey or the control panel.
+#[derive(Message, Debug, Clone)]
+pub struct EnterBuildMode {
+    pub building_id: String,
+}
+
+fn building_hotkeys(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    registry: Res<BuildingRegistry>,
+    mut enter_build_mode: MessageWriter<EnterBuildMode>,
+) {
+    for (id, entry) in &registry.buildings {
+        if let Some(hotkey) = entry.hotkey
+            && keyboard_input.just_pressed(hotkey)
+        {
+            enter_build_mode.write(EnterBuildMode {
+                building_id: id.clone(),
+            });
+        }
+    }
+}
+
+fn enter_build_mode(
+    mut requests: MessageReader<EnterBuildMode>,
+    registry: Res<BuildingRegistry>,
+    mut cursor_building: ResMut<CursorBuilding>,
+    mut next_mode: ResMut<NextState<InputMode>>,
+) {
+    // only the most recent request matters
+    let Some(request) = requests.read().last() else {
+        return;
+    };
+    if !registry.buildings.contains_key(&request.building_id) {
+        warn!("Cannot build unknown building '{}'", request.building_id);
+        return;
+    }
+    cursor_building.building_id = Some(request.building_id.clone());
+    next_mode.set(InputMode::Build);
+}
+
+fn exit_build_mode(mut cursor_building: ResMut<CursorBuilding>) {
+    cursor_building.building_id = None;
+}
+
+/// Escape or right click leave build mode, R rotates the [`CursorBuilding`].
+fn build_mode_controls(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    mut cursor_building: ResMut<CursorBuilding>,
+    mut next_mode: ResMut<NextState<InputMode>>,
+) {
+    if keyboard_input.just_pressed(key_bindings::CANCEL)
+        || mouse_input.just_pressed(MouseButton::Right)
+    {
+        next_mode.set(InputMode::Normal);
+        return;
+    }
+
+    if keyboard_input.just_pressed(key_bindings::ROTATE_BUILDING) {
+        cursor_building.rotation = cursor_building.rotation.next();
+    }
+}
+
+/// Left click places the [`CursorBuilding`] under the mouse cursor.
+#[allow(clippy::too_many_arguments)]
+fn place_cursor_building(
+    mut commands: Commands,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    mut map: ResMut<Map>,
+    terrain: Res<TerrainRegistry>,
+    registry: Res<BuildingRegistry>,
+    mut toasts: MessageWriter<ToastMessage>,
+    cursor: Res<MouseCursor>,
+    cursor_building: Res<CursorBuilding>,
+    mut next_mode: ResMut<NextState<InputMode>>,
+) {
+    if !mouse_input.just_pressed(MouseButton::Left) {
+        return;
+    }
+    let Some(building_id) = &cursor_building.building_id else {
+        next_mode.set(InputMode::Normal);
+        return;
+    };
+    let (Some(world_pos), Some(entry)) =
+        (cursor.world_position(), registry.buildings.get(building_id))
+    else {
+        return;
+    };
+
+    let rotation = cursor_building.rotation;
+    let global_pos = entry.origin_for_cursor(world_pos, rotation);
+    match registry.place(
+        building_id,
+        global_pos,
+        rotation,
+        &mut map,
+        &terrain,
+        &mut commands,
+    ) {
+        Ok(_) => {
+            toasts.write(ToastMessage {
+                content: format!("Placed {} at {}", building_id, global_pos),
+            });
+            // hold shift to keep placing the same building
+            if !keyboard_input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+                next_mode.set(InputMode::Normal);
+            }
+        }
+        Err(error) => {
+            toasts.write(ToastMessage {
+                content: format!(
+                    "Failed to place {} at {}: {}",
+                    building_id, global_pos, error
+                ),
+            });
+        }
+    }
+}
+
+/// Translucent preview of the [`CursorBuilding`] under the mouse cursor.
+#[derive(Component)]
+struct BuildGhost;
+
+#[derive(Resource)]
+struct BuildGhostMaterials {
+    valid: Handle<ColorMaterial>,
+    blocked: Handle<ColorMaterial>,
+}
+
+const BUILD_GHOST_Z: f32 = 1.0;
+
+type BuildGhostData = (
+    &'static mut Transform,
+    &'static mut Mesh2d,
+    &'static mut MeshMaterial2d<ColorMaterial>,
+    &'static mut Visibility,
+);
+
+fn setup_build_ghost(mut commands: Commands, mut materials: ResMut<Assets<ColorMaterial>>) {
+    let valid = materials.add(ColorMaterial::from_color(Color::srgba(0.2, 0.9, 0.2, 0.5)));
+    let blocked = materials.add(ColorMaterial::from_color(Color::srgba(0.9, 0.2, 0.2, 0.5)));
+    commands.spawn((
+        BuildGhost,
+        Transform::from_translation(Vec3::Z * BUILD_GHOST_Z),
+        GlobalTransform::default(),
+        Mesh2d(Handle::default()),
+        MeshMaterial2d(valid.clone()),
+        Visibility::Hidden,
+    ));
+    commands.insert_resource(BuildGhostMaterials { valid, blocked });
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_build_ghost(
+    mut gizmos: Gizmos,
+    ghost: Single<BuildGhostData, With<BuildGhost>>,
+    ghost_materials: Res<BuildGhostMaterials>,
+    cursor_building: Res<CursorBuilding>,
+    cursor: Res<MouseCursor>,
+    registry: Res<BuildingRegistry>,
+    map: Res<Map>,
+    terrain: Res<TerrainRegistry>,
+) {
+    let (mut transform, mut mesh, mut material, mut visibility) = ghost.into_inner();
+    let (Some(building_id), Some(world_pos)) =
+        (&cursor_building.building_id, cursor.world_position())
+    else {
+        *visibility = Visibility::Hidden;
+        return;
+    };
+    let Some(entry) = registry.buildings.get(building_id) else {
+        *visibility = Visibility::Hidden;
+        return;
+    };
+
+    let rotation = cursor_building.rotation;
+    let origin = entry.origin_for_cursor(world_pos, rotation);
+    *transform = entry.transform(origin, rotation);
+    transform.translation.z = BUILD_GHOST_Z;
+    if mesh.0 != entry.mesh_handle {
+        mesh.0 = entry.mesh_handle.clone();
+    }
+    *visibility = Visibility::Visible;
+
+    match map.can_place(origin, &entry.rotated_occlusion_map(rotation), &terrain) {
+        Ok(()) => material.0 = ghost_materials.valid.clone(),
+        Err(errors) => {
+            material.0 = ghost_materials.blocked.clone();
+            for error in errors {
+                let tile_center =
+                    error.position().as_vec2() * FIELD_SIZE + Vec2::splat(FIELD_SIZE / 2.0);
+                gizmos.rect_2d(
+                    Isometry2d::from_translation(tile_center),
+                    Vec2::splat(FIELD_SIZE * 0.9),
+                    Color::srgba(1.0, 0.0, 0.0, 0.8),
+                );
+            }
+        }
+    }
+}
+
+pub struct BuildModePlugin;
+
+impl Plugin for BuildModePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CursorBuilding>()
+            .add_message::<EnterBuildMode>()
+            .add_systems(Startup, setup_build_ghost)
+            .add_systems(OnExit(InputMode::Build), exit_build_mode)
+            .add_systems(
+                Update,
+                (
+                    building_hotkeys
+                        .run_if(in_state(InputMode::Normal).or(in_state(InputMode::Build))),
+                    enter_build_mode,
+                    build_mode_controls.run_if(in_state(InputMode::Build)),
+                    place_cursor_building
+                        .run_if(in_state(InputMode::Build).and(not(control_panel_hovered))),
+                    update_build_ghost,
+                )
+                    .chain(),
+            );
+    }
+}
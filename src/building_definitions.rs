@@ -0,0 +1,252 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::{
+    ecs::system::{EntityCommands, SystemParam},
+    prelude::*,
+};
+use serde::Deserialize;
+
+use crate::{
+    BuildingBuilder, BuildingEntry, BuildingRotation, graphics::create_polygon_mesh, key_bindings,
+    map::FIELD_SIZE, module_loader::RonAssetLoader, user_controls::parse_key_code,
+};
+
+/// Shape of the mesh used to render a building.
+#[derive(Debug, Clone, Deserialize)]
+pub enum BuildingShape {
+    /// Regular polygon with `sides` corners and a circumradius of `size` tiles.
+    Polygon { sides: usize, size: f32 },
+    /// Mesh asset loaded from the given asset path.
+    Mesh(String),
+}
+
+/// Building definition as written in a `*.building.ron` asset file of a content module.
+#[derive(Asset, TypePath, Debug, Clone, Deserialize)]
+pub struct BuildingDefinition {
+    /// Namespaced registry id, e.g. `core:barracks`.
+    pub id: String,
+    /// Tiles occupied by the building as `(x, y)` offsets from its origin tile.
+    pub footprint: Vec<(i32, i32)>,
+    /// Offset applied to the cursor before snapping it to the grid, in world units.
+    /// Defaults to centering the cursor on the footprint.
+    #[serde(default)]
+    pub build_cursor_offset: Option<(f32, f32)>,
+    pub shape: BuildingShape,
+    /// Color as `(red, green, blue)` in sRGB.
+    pub color: (f32, f32, f32),
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub cost: u32,
+    /// Construction time in seconds.
+    #[serde(default)]
+    pub build_time: f32,
+    /// Name of the [`KeyCode`] that selects the building, e.g. `"KeyB"`.
+    #[serde(default)]
+    pub hotkey: Option<String>,
+    /// Ids of components from the [`BuildingComponentRegistry`] attached to spawned buildings.
+    #[serde(default)]
+    pub components: Vec<String>,
+}
+
+impl BuildingDefinition {
+    /// Checks the definition for problems that parsing alone cannot catch.
+    /// Returns a list of human readable problems if the definition is invalid.
+    fn validate(&self, components: &BuildingComponentRegistry) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+        match self.id.split_once(':') {
+            Some((namespace, name)) if !namespace.is_empty() && !name.is_empty() => {}
+            _ => problems.push(format!(
+                "id '{}' must have the form 'namespace:name'",
+                self.id
+            )),
+        }
+        if self.footprint.is_empty() {
+            problems.push("footprint must contain at least one tile".to_string());
+        }
+        let mut seen = HashSet::new();
+        for offset in &self.footprint {
+            if !seen.insert(*offset) {
+                problems.push(format!(
+                    "footprint contains tile {:?} more than once",
+                    offset
+                ));
+            }
+        }
+        if let Some(hotkey) = &self.hotkey {
+            match parse_key_code(hotkey) {
+                None => problems.push(format!("unknown hotkey '{}'", hotkey)),
+                Some(key) if key_bindings::reserved_keys().any(|reserved| reserved == key) => {
+                    problems.push(format!(
+                        "hotkey '{}' is already bound to another control",
+                        hotkey
+                    ))
+                }
+                Some(_) => {}
+            }
+        }
+        if let BuildingShape::Polygon { sides, size } = self.shape {
+            if sides < 3 {
+                problems.push(format!("polygon needs at least 3 sides, got {}", sides));
+            }
+            if size <= 0.0 {
+                problems.push(format!("polygon size must be positive, got {}", size));
+            }
+        }
+        if self.build_time < 0.0 {
+            problems.push(format!(
+                "build time must not be negative, got {}",
+                self.build_time
+            ));
+        }
+        for component in &self.components {
+            if !components.components.contains_key(component) {
+                problems.push(format!("unknown component '{}'", component));
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+
+    fn occlusion_map(&self) -> Vec<IVec2> {
+        self.footprint
+            .iter()
+            .map(|&(x, y)| IVec2::new(x, y))
+            .collect()
+    }
+
+    /// Cursor offset that centers the build cursor on the footprint.
+    fn centered_cursor_offset(&self) -> Vec2 {
+        let sum = self
+            .occlusion_map()
+            .iter()
+            .fold(Vec2::ZERO, |sum, offset| sum + offset.as_vec2());
+        -sum / self.footprint.len() as f32 * FIELD_SIZE
+    }
+
+    /// Creates the registry entry for this definition.
+    /// The definition must have passed [`BuildingDefinition::validate`].
+    fn to_entry(
+        &self,
+        asset_server: &AssetServer,
+        meshes: &mut Assets<Mesh>,
+        materials: &mut Assets<ColorMaterial>,
+        components: &BuildingComponentRegistry,
+    ) -> BuildingEntry {
+        let mesh_handle = match &self.shape {
+            BuildingShape::Polygon { sides, size } => {
+                meshes.add(create_polygon_mesh(*sides, size * FIELD_SIZE))
+            }
+            BuildingShape::Mesh(path) => asset_server.load(path.clone()),
+        };
+        let (red, green, blue) = self.color;
+        let material_handle =
+            materials.add(ColorMaterial::from_color(Color::srgb(red, green, blue)));
+        BuildingEntry {
+            occlusion_map: self.occlusion_map(),
+            hotkey: self.hotkey.as_deref().and_then(parse_key_code),
+            build_cursor_offset: self
+                .build_cursor_offset
+                .map(|(x, y)| Vec2::new(x, y))
+                .unwrap_or_else(|| self.centered_cursor_offset()),
+            mesh_handle,
+            material_handle,
+            description: self.description.clone(),
+            cost: self.cost,
+            build_time: self.build_time,
+            builder: Box::new(DefinitionBuilder {
+                components: self
+                    .components
+                    .iter()
+                    .map(|id| components.components[id])
+                    .collect(),
+            }),
+        }
+    }
+}
+
+/// Inserts a component into a freshly spawned building.
+pub type InsertComponentFn = fn(&mut EntityCommands);
+
+/// Components that building definitions can attach by id.
+/// Components are code, so they have to be registered from Rust before definitions are loaded.
+#[derive(Resource, Default)]
+pub struct BuildingComponentRegistry {
+    components: HashMap<String, InsertComponentFn>,
+}
+
+impl BuildingComponentRegistry {
+    pub fn register(&mut self, id: impl Into<String>, insert: InsertComponentFn) {
+        let id = id.into();
+        if self.components.insert(id.clone(), insert).is_some() {
+            warn!("Existing building component '{}' was overwritten.", id);
+        }
+    }
+}
+
+/// Builder for buildings created from a [`BuildingDefinition`].
+struct DefinitionBuilder {
+    components: Vec<InsertComponentFn>,
+}
+
+impl BuildingBuilder for DefinitionBuilder {
+    fn build(
+        &self,
+        entry: &BuildingEntry,
+        commands: &mut Commands,
+        position: IVec2,
+        rotation: BuildingRotation,
+    ) -> Entity {
+        let mut entity = commands.spawn((
+            entry.transform(position, rotation),
+            GlobalTransform::default(),
+            Mesh2d(entry.mesh_handle.clone()),
+            MeshMaterial2d(entry.material_handle.clone()),
+        ));
+        for insert in &self.components {
+            insert(&mut entity);
+        }
+        entity.id()
+    }
+}
+
+/// Everything needed to turn a [`BuildingDefinition`] into a [`BuildingEntry`].
+#[derive(SystemParam)]
+pub struct BuildingEntryFactory<'w> {
+    asset_server: Res<'w, AssetServer>,
+    meshes: ResMut<'w, Assets<Mesh>>,
+    materials: ResMut<'w, Assets<ColorMaterial>>,
+    components: Res<'w, BuildingComponentRegistry>,
+}
+
+impl BuildingEntryFactory<'_> {
+    /// Checks the definition for problems that parsing alone cannot catch.
+    /// Returns a list of human readable problems if the definition is invalid.
+    pub fn validate(&self, definition: &BuildingDefinition) -> Result<(), Vec<String>> {
+        definition.validate(&self.components)
+    }
+
+    /// Creates the registry entry of a definition, adding its mesh and material.
+    /// The definition must have passed [`BuildingEntryFactory::validate`].
+    pub fn create(&mut self, definition: &BuildingDefinition) -> BuildingEntry {
+        definition.to_entry(
+            &self.asset_server,
+            &mut self.meshes,
+            &mut self.materials,
+            &self.components,
+        )
+    }
+}
+
+pub struct BuildingDefinitionsPlugin;
+
+impl Plugin for BuildingDefinitionsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<BuildingDefinition>()
+            .register_asset_loader(RonAssetLoader::<BuildingDefinition>::new(&["building.ron"]))
+            .init_resource::<BuildingComponentRegistry>();
+    }
+}
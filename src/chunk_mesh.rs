@@ -0,0 +1,61 @@
+use bevy::prelude::*;
+
+use crate::{
+    graphics::create_tile_grid_mesh,
+    map::{CHUNK_SIZE, CHUNK_SIZE_F32, FIELD_SIZE, Map},
+    terrain::TerrainRegistry,
+};
+
+/// Depth of chunk meshes, below buildings and everything else drawn on the map.
+const CHUNK_MESH_Z: f32 = -1.0;
+
+/// Material shared by all chunk meshes; the tiles are colored through vertex colors.
+#[derive(Resource)]
+struct ChunkMeshMaterial(Handle<ColorMaterial>);
+
+fn setup_chunk_mesh_material(mut commands: Commands, mut materials: ResMut<Assets<ColorMaterial>>) {
+    let material = materials.add(ColorMaterial::from_color(Color::WHITE));
+    commands.insert_resource(ChunkMeshMaterial(material));
+}
+
+/// Gives every [`crate::map::ChunkEntity`] a mesh with one colored quad per tile.
+///
+/// Meshes are only rebuilt for chunks whose terrain changed, and for every loaded chunk
+/// when the terrain kinds themselves changed, e.g. because a definition was reloaded.
+fn update_chunk_meshes(
+    mut commands: Commands,
+    mut map: ResMut<Map>,
+    terrain: Res<TerrainRegistry>,
+    material: Res<ChunkMeshMaterial>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    let mut changed_chunks = map.take_changed_chunks();
+    if terrain.is_changed() {
+        changed_chunks = map.loaded_chunks().collect();
+    }
+    for chunk_pos in changed_chunks {
+        let (Some(entity), Some(chunk_terrain)) =
+            (map.chunk_entity(chunk_pos), map.chunk_terrain(chunk_pos))
+        else {
+            continue;
+        };
+        let mesh = create_tile_grid_mesh(CHUNK_SIZE, CHUNK_SIZE, FIELD_SIZE, |x, y| {
+            terrain.get(chunk_terrain[x][y]).color()
+        });
+        let chunk_origin = chunk_pos.as_vec2() * CHUNK_SIZE_F32 * FIELD_SIZE;
+        commands.entity(entity).insert((
+            Transform::from_translation(chunk_origin.extend(CHUNK_MESH_Z)),
+            Mesh2d(meshes.add(mesh)),
+            MeshMaterial2d(material.0.clone()),
+        ));
+    }
+}
+
+pub struct ChunkMeshPlugin;
+
+impl Plugin for ChunkMeshPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup_chunk_mesh_material)
+            .add_systems(Update, update_chunk_meshes);
+    }
+}
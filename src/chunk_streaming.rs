@@ -0,0 +1,124 @@
+use bevy::prelude::*;
+
+use crate::{
+    AppState,
+    map::{CHUNK_SIZE_F32, FIELD_SIZE, Map, WORLD_HALF_EXTENT_CHUNKS},
+    module_loader::modules_applied,
+    player_camera::PlayerCamera,
+    terrain::{TerrainPalette, TerrainRegistry, WorldGenerator},
+};
+
+/// Controls which chunks are kept loaded around the [`PlayerCamera`].
+#[derive(Resource, Debug, Clone)]
+pub struct ChunkStreamingSettings {
+    /// Number of chunks loaded beyond the edges of the camera view.
+    pub load_radius: i32,
+    /// Number of chunks beyond the edges of the camera view after which loaded chunks are
+    /// unloaded again. Must not be smaller than `load_radius`; the gap keeps chunks at the
+    /// border from being loaded and unloaded over and over while the camera moves.
+    pub unload_radius: i32,
+}
+
+impl Default for ChunkStreamingSettings {
+    fn default() -> Self {
+        Self {
+            load_radius: 1,
+            unload_radius: 3,
+        }
+    }
+}
+
+/// World space area covered by the camera view.
+pub fn visible_world_rect(camera: &Camera, camera_transform: &GlobalTransform) -> Option<Rect> {
+    let viewport = camera.logical_viewport_rect()?;
+    let corners = [
+        viewport.min,
+        viewport.max,
+        Vec2::new(viewport.min.x, viewport.max.y),
+        Vec2::new(viewport.max.x, viewport.min.y),
+    ];
+    let mut world_rect = Rect::EMPTY;
+    for corner in corners {
+        let world_pos = camera.viewport_to_world_2d(camera_transform, corner).ok()?;
+        world_rect = world_rect.union_point(world_pos);
+    }
+    Some(world_rect)
+}
+
+/// Range of chunk positions covered by the camera view, inclusive on both ends.
+pub fn visible_chunks(camera: &Camera, camera_transform: &GlobalTransform) -> Option<IRect> {
+    let world_rect = visible_world_rect(camera, camera_transform)?;
+    let chunk_world_size = CHUNK_SIZE_F32 * FIELD_SIZE;
+    Some(IRect::from_corners(
+        (world_rect.min / chunk_world_size).floor().as_ivec2(),
+        (world_rect.max / chunk_world_size).floor().as_ivec2(),
+    ))
+}
+
+/// Loads the chunks around the camera view and unloads those far away from it.
+/// Runs once all content modules were applied, so the terrain kinds are known.
+fn stream_chunks(
+    mut commands: Commands,
+    mut map: ResMut<Map>,
+    settings: Res<ChunkStreamingSettings>,
+    generator: Res<WorldGenerator>,
+    terrain: Res<TerrainRegistry>,
+    camera_query: Single<(&Camera, &GlobalTransform), With<PlayerCamera>>,
+) {
+    let palette = match TerrainPalette::resolve(&terrain) {
+        Ok(palette) => palette,
+        Err(error) => {
+            error_once!("Cannot generate chunks: {}", error);
+            return;
+        }
+    };
+    let (camera, camera_transform) = camera_query.into_inner();
+    let Some(visible) = visible_chunks(camera, camera_transform) else {
+        return;
+    };
+
+    let unload_area = visible.inflate(settings.unload_radius.max(settings.load_radius));
+    let far_away: Vec<IVec2> = map
+        .loaded_chunks()
+        .filter(|chunk_pos| !unload_area.contains(*chunk_pos))
+        .collect();
+    for chunk_pos in far_away {
+        map.unload_chunk(
+            chunk_pos,
+            |chunk_pos| generator.generate_chunk(chunk_pos, &palette),
+            &mut commands,
+        );
+        debug!("Unloaded chunk at {}", chunk_pos);
+    }
+
+    let world_area = IRect::new(
+        -WORLD_HALF_EXTENT_CHUNKS,
+        -WORLD_HALF_EXTENT_CHUNKS,
+        WORLD_HALF_EXTENT_CHUNKS - 1,
+        WORLD_HALF_EXTENT_CHUNKS - 1,
+    );
+    let load_area = visible.inflate(settings.load_radius).intersect(world_area);
+    for x in load_area.min.x..=load_area.max.x {
+        for y in load_area.min.y..=load_area.max.y {
+            let chunk_pos = IVec2::new(x, y);
+            if map.load_chunk(
+                chunk_pos,
+                |chunk_pos| generator.generate_chunk(chunk_pos, &palette),
+                &mut commands,
+            ) {
+                debug!("Loaded chunk at {}", chunk_pos);
+            }
+        }
+    }
+}
+
+pub struct ChunkStreamingPlugin;
+
+impl Plugin for ChunkStreamingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ChunkStreamingSettings>().add_systems(
+            Update,
+            stream_chunks.run_if(in_state(AppState::Game).and(modules_applied)),
+        );
+    }
+}
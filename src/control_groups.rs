@@ -0,0 +1,113 @@
+use bevy::prelude::*;
+
+use crate::{AppState, InputMode, key_bindings, player_camera::PlayerCamera, selection::Selection};
+
+/// Number of control groups, one for every digit key.
+pub const GROUP_COUNT: usize = key_bindings::CONTROL_GROUPS.len();
+/// Seconds between two presses of a group key that make a double tap.
+const DOUBLE_TAP_TIME: f32 = 0.3;
+
+/// Selections stored under the number keys.
+#[derive(Resource, Debug, Default)]
+pub struct ControlGroups {
+    groups: [Vec<Entity>; GROUP_COUNT],
+}
+
+impl ControlGroups {
+    /// Entities of the group at `index`, which must be below [`GROUP_COUNT`].
+    pub fn get(&self, index: usize) -> &[Entity] {
+        &self.groups[index]
+    }
+
+    /// Replaces the group at `index`, which must be below [`GROUP_COUNT`].
+    pub fn set(&mut self, index: usize, entities: Vec<Entity>) {
+        self.groups[index] = entities;
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Handles the group keys:
+/// - ctrl + number assigns the selection to the group,
+/// - shift + number adds the selection to the group,
+/// - number selects the group, pressing it twice quickly also centers the camera on it.
+fn control_group_hotkeys(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    time: Res<Time>,
+    mut groups: ResMut<ControlGroups>,
+    mut selection: ResMut<Selection>,
+    transforms: Query<&Transform>,
+    mut camera: Single<&mut PlayerCamera>,
+    mut last_recall: Local<Option<(usize, f32)>>,
+) {
+    let ctrl = keyboard_input.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    let shift = keyboard_input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    for (index, &key) in key_bindings::CONTROL_GROUPS.iter().enumerate() {
+        if !keyboard_input.just_pressed(key) {
+            continue;
+        }
+        if ctrl {
+            groups.groups[index] = selection.entities().to_vec();
+        } else if shift {
+            let group = &mut groups.groups[index];
+            for &entity in selection.entities() {
+                if !group.contains(&entity) {
+                    group.push(entity);
+                }
+            }
+        } else {
+            let group = &groups.groups[index];
+            if group.is_empty() {
+                continue;
+            }
+            let now = time.elapsed_secs();
+            let double_tap =
+                last_recall.is_some_and(|(last, at)| last == index && now - at <= DOUBLE_TAP_TIME);
+            *last_recall = Some((index, now));
+            selection.set(group.iter().copied());
+            if double_tap {
+                let positions: Vec<Vec2> = transforms
+                    .iter_many(group)
+                    .map(|transform| transform.translation.truncate())
+                    .collect();
+                if !positions.is_empty() {
+                    let center = positions.iter().sum::<Vec2>() / positions.len() as f32;
+                    let (_, scale) = camera.target_view();
+                    camera.set_target_view(center, scale);
+                }
+            }
+        }
+    }
+}
+
+/// Removes despawned entities from the groups.
+fn prune_control_groups(mut groups: ResMut<ControlGroups>, entities: Query<()>) {
+    let outdated = groups
+        .groups
+        .iter()
+        .flatten()
+        .any(|&entity| !entities.contains(entity));
+    if outdated {
+        for group in &mut groups.groups {
+            group.retain(|&entity| entities.contains(entity));
+        }
+    }
+}
+
+pub struct ControlGroupsPlugin;
+
+impl Plugin for ControlGroupsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ControlGroups>().add_systems(
+            Update,
+            (
+                prune_control_groups,
+                control_group_hotkeys.run_if(in_state(InputMode::Normal)),
+            )
+                .chain()
+                .run_if(in_state(AppState::Game)),
+        );
+    }
+}
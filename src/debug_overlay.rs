@@ -0,0 +1,298 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+
+use crate::{
+    AppState, MouseCursor, PlacedBuilding,
+    chunk_streaming::visible_chunks,
+    key_bindings,
+    map::{CHUNK_HALF_SIZE, CHUNK_SIZE, CHUNK_SIZE_F32, ChunkEntity, FIELD_SIZE, Map},
+    pathfinding::movement_cost,
+    player_camera::PlayerCamera,
+    terrain::TerrainRegistry,
+    toasts::ToastMessage,
+};
+
+/// Debug overlays drawn on top of the map, each toggled by its own function key.
+#[derive(Resource, Debug, Clone, Copy, Default)]
+pub struct DebugOverlays {
+    /// Outline of every loaded chunk. Toggled with F1.
+    pub chunk_bounds: bool,
+    /// Free and occupied tiles. Toggled with F2.
+    pub occupancy: bool,
+    /// Buildability and vision blocking of the terrain. Toggled with F3.
+    pub terrain: bool,
+    /// Tiles units can move across and the connections between them,
+    /// computed paths and flow fields.
+    /// While enabled, right-click sets the start and shift + right-click the goal of a
+    /// debug path. Toggled with F4.
+    pub pathfinding: bool,
+    /// Entity id of every placed building. Toggled with F5.
+    pub entity_ids: bool,
+}
+
+fn toggle_debug_overlays(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut overlays: ResMut<DebugOverlays>,
+    mut toasts: MessageWriter<ToastMessage>,
+) {
+    let overlays = &mut *overlays;
+    let toggles = [
+        (
+            key_bindings::CHUNK_BOUNDS_OVERLAY,
+            "chunk bounds",
+            &mut overlays.chunk_bounds,
+        ),
+        (
+            key_bindings::OCCUPANCY_OVERLAY,
+            "occupancy",
+            &mut overlays.occupancy,
+        ),
+        (
+            key_bindings::TERRAIN_OVERLAY,
+            "terrain",
+            &mut overlays.terrain,
+        ),
+        (
+            key_bindings::PATHFINDING_OVERLAY,
+            "pathfinding grid",
+            &mut overlays.pathfinding,
+        ),
+        (
+            key_bindings::ENTITY_IDS_OVERLAY,
+            "entity ids",
+            &mut overlays.entity_ids,
+        ),
+    ];
+    for (key, name, enabled) in toggles {
+        if keyboard_input.just_pressed(key) {
+            *enabled = !*enabled;
+            toasts.write(ToastMessage {
+                content: format!(
+                    "Debug overlay '{}' {}",
+                    name,
+                    if *enabled { "on" } else { "off" }
+                ),
+            });
+        }
+    }
+}
+
+/// Chunks visible to the [`PlayerCamera`]; overlays skip all other chunks.
+fn visible_chunk_entities<'a>(
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+    chunks: &'a Query<&ChunkEntity>,
+) -> impl Iterator<Item = &'a ChunkEntity> {
+    let visible = visible_chunks(camera, camera_transform);
+    chunks
+        .iter()
+        .filter(move |chunk| visible.is_some_and(|visible| visible.contains(chunk.position())))
+}
+
+/// Center of the tile at `local_pos` of the chunk at `chunk_pos`, in world space.
+fn tile_center(chunk_pos: IVec2, local_pos: IVec2) -> Vec2 {
+    Map::tile_center(Map::chunk_to_global(chunk_pos, local_pos))
+}
+
+fn debug_chunk_bounds(
+    mut gizmos: Gizmos,
+    query: Query<&ChunkEntity>,
+    camera_query: Single<(&Camera, &GlobalTransform), With<PlayerCamera>>,
+) {
+    let (camera, camera_transform) = camera_query.into_inner();
+    for chunk in visible_chunk_entities(camera, camera_transform, &query) {
+        let chunk_world_pos =
+            chunk.position().as_vec2() * CHUNK_SIZE_F32 * FIELD_SIZE + CHUNK_HALF_SIZE;
+        gizmos
+            .grid_2d(
+                Isometry2d::from_translation(chunk_world_pos),
+                UVec2::splat(1),
+                Vec2::splat(CHUNK_SIZE_F32 * FIELD_SIZE),
+                Color::srgba(0.0, 0.7, 0.0, 0.5),
+            )
+            .outer_edges();
+    }
+}
+
+fn debug_chunk_fields(
+    mut gizmos: Gizmos,
+    query: Query<&ChunkEntity>,
+    camera_query: Single<(&Camera, &GlobalTransform), With<PlayerCamera>>,
+    map: Res<Map>,
+    cursor: Res<MouseCursor>,
+) {
+    let (camera, camera_transform) = camera_query.into_inner();
+    let color_occupied = Color::srgba(0.7, 0.0, 0.0, 0.4);
+    let color_free = Color::srgba(0.0, 0.7, 0.0, 0.2);
+    let color_hover_occupied = Color::srgba(1.0, 0.3, 0.0, 0.6);
+    let color_hover_free = Color::srgba(0.0, 0.3, 1.0, 0.6);
+    for chunk in visible_chunk_entities(camera, camera_transform, &query) {
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                let local_pos = IVec2::new(x as i32, y as i32);
+                let hovered = cursor.grid_position() == Some((chunk.position(), local_pos));
+                let color = if map.is_occupied(chunk.position(), local_pos) {
+                    if hovered {
+                        color_hover_occupied
+                    } else {
+                        color_occupied
+                    }
+                } else if hovered {
+                    color_hover_free
+                } else {
+                    color_free
+                };
+                gizmos.circle_2d(
+                    Isometry2d::from_translation(tile_center(chunk.position(), local_pos)),
+                    FIELD_SIZE / 4.0,
+                    color,
+                );
+            }
+        }
+    }
+}
+
+/// Marks unbuildable tiles, and crosses out tiles that block vision.
+fn debug_terrain(
+    mut gizmos: Gizmos,
+    query: Query<&ChunkEntity>,
+    camera_query: Single<(&Camera, &GlobalTransform), With<PlayerCamera>>,
+    map: Res<Map>,
+    terrain: Res<TerrainRegistry>,
+) {
+    let (camera, camera_transform) = camera_query.into_inner();
+    let color_unbuildable = Color::srgba(0.9, 0.5, 0.0, 0.6);
+    let color_blocks_vision = Color::srgba(0.2, 0.2, 0.2, 0.8);
+    for chunk in visible_chunk_entities(camera, camera_transform, &query) {
+        let Some(chunk_terrain) = map.chunk_terrain(chunk.position()) else {
+            continue;
+        };
+        for (x, column) in chunk_terrain.iter().enumerate() {
+            for (y, &terrain_id) in column.iter().enumerate() {
+                let kind = terrain.get(terrain_id);
+                let center = tile_center(chunk.position(), IVec2::new(x as i32, y as i32));
+                if !kind.buildable {
+                    gizmos.rect_2d(
+                        Isometry2d::from_translation(center),
+                        Vec2::splat(FIELD_SIZE * 0.8),
+                        color_unbuildable,
+                    );
+                }
+                if kind.blocks_vision {
+                    let corner = Vec2::splat(FIELD_SIZE * 0.3);
+                    gizmos.line_2d(center - corner, center + corner, color_blocks_vision);
+                    gizmos.line_2d(
+                        center + corner.with_x(-corner.x),
+                        center - corner.with_x(-corner.x),
+                        color_blocks_vision,
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Connects neighboring tiles units can move across, brighter for cheaper movement.
+fn debug_pathfinding_grid(
+    mut gizmos: Gizmos,
+    query: Query<&ChunkEntity>,
+    camera_query: Single<(&Camera, &GlobalTransform), With<PlayerCamera>>,
+    map: Res<Map>,
+    terrain: Res<TerrainRegistry>,
+) {
+    let (camera, camera_transform) = camera_query.into_inner();
+    let movement_cost = |global_pos: IVec2| movement_cost(&map, &terrain, global_pos);
+    for chunk in visible_chunk_entities(camera, camera_transform, &query) {
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                let local_pos = IVec2::new(x as i32, y as i32);
+                let global_pos = Map::chunk_to_global(chunk.position(), local_pos);
+                let Some(cost) = movement_cost(global_pos) else {
+                    continue;
+                };
+                let center = tile_center(chunk.position(), local_pos);
+                for direction in [IVec2::X, IVec2::Y] {
+                    let Some(neighbor_cost) = movement_cost(global_pos + direction) else {
+                        continue;
+                    };
+                    let brightness = 2.0 / (cost + neighbor_cost);
+                    gizmos.line_2d(
+                        center,
+                        center + direction.as_vec2() * FIELD_SIZE,
+                        Color::srgba(0.2, 0.4 + 0.6 * brightness, 1.0, 0.5),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Text label showing the entity id of its target.
+#[derive(Component)]
+struct EntityIdLabel {
+    target: Entity,
+}
+
+/// Depth of entity id labels, above everything else drawn on the map.
+const ENTITY_ID_LABEL_Z: f32 = 10.0;
+
+/// Keeps one [`EntityIdLabel`] on top of every placed building while the overlay is enabled.
+fn update_entity_id_labels(
+    mut commands: Commands,
+    overlays: Res<DebugOverlays>,
+    mut labels: Query<(Entity, &EntityIdLabel, &mut Transform)>,
+    targets: Query<(Entity, &GlobalTransform), With<PlacedBuilding>>,
+) {
+    let mut labeled = HashSet::new();
+    for (label, EntityIdLabel { target }, mut transform) in &mut labels {
+        match targets.get(*target) {
+            Ok((_, target_transform)) if overlays.entity_ids => {
+                transform.translation = target_transform
+                    .translation()
+                    .truncate()
+                    .extend(ENTITY_ID_LABEL_Z);
+                labeled.insert(*target);
+            }
+            _ => commands.entity(label).despawn(),
+        }
+    }
+    if !overlays.entity_ids {
+        return;
+    }
+    for (target, target_transform) in &targets {
+        if labeled.contains(&target) {
+            continue;
+        }
+        commands.spawn((
+            EntityIdLabel { target },
+            Text2d::new(target.to_string()),
+            TextFont::from_font_size(6.0),
+            Transform::from_translation(
+                target_transform
+                    .translation()
+                    .truncate()
+                    .extend(ENTITY_ID_LABEL_Z),
+            ),
+        ));
+    }
+}
+
+pub struct DebugOverlayPlugin;
+
+impl Plugin for DebugOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DebugOverlays>().add_systems(
+            Update,
+            (
+                toggle_debug_overlays,
+                debug_chunk_bounds.run_if(|overlays: Res<DebugOverlays>| overlays.chunk_bounds),
+                debug_chunk_fields.run_if(|overlays: Res<DebugOverlays>| overlays.occupancy),
+                debug_terrain.run_if(|overlays: Res<DebugOverlays>| overlays.terrain),
+                debug_pathfinding_grid.run_if(|overlays: Res<DebugOverlays>| overlays.pathfinding),
+                update_entity_id_labels,
+            )
+                .run_if(in_state(AppState::Game)),
+        );
+    }
+}
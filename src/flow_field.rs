@@ -0,0 +1,374 @@
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use bevy::prelude::*;
+
+use crate::{
+    AppState,
+    chunk_streaming::visible_chunks,
+    debug_overlay::DebugOverlays,
+    map::{CHUNK_SIZE, FIELD_SIZE, Map},
+    pathfinding::{OpenTile, movement_cost},
+    player_camera::PlayerCamera,
+    terrain::TerrainRegistry,
+};
+
+/// Neighbors a flow field can point to, orthogonal ones first.
+const DIRECTIONS: [IVec2; 8] = [
+    IVec2::X,
+    IVec2::NEG_X,
+    IVec2::Y,
+    IVec2::NEG_Y,
+    IVec2::new(1, 1),
+    IVec2::new(1, -1),
+    IVec2::new(-1, 1),
+    IVec2::new(-1, -1),
+];
+
+/// Flow field data of one loaded chunk, indexed by local `[x][y]`.
+struct FlowFieldChunk {
+    /// Summed movement cost to the goal, infinite for unreachable tiles.
+    integration: [[f32; CHUNK_SIZE]; CHUNK_SIZE],
+    /// Step towards the goal, zero at the goal and on unreachable tiles.
+    directions: [[IVec2; CHUNK_SIZE]; CHUNK_SIZE],
+}
+
+/// Direction towards one goal for every tile of the loaded chunks.
+///
+/// Built once per goal and shared by all entities heading there, which is much cheaper
+/// than searching a separate path for each of them.
+pub struct FlowField {
+    /// [`Map::navigation_revision`] the field was built at.
+    revision: u64,
+    chunks: HashMap<IVec2, FlowFieldChunk>,
+}
+
+impl FlowField {
+    /// Builds the integration field with Dijkstra's algorithm spreading out from the goal,
+    /// then points every tile to the neighbor it reaches the goal most cheaply through.
+    ///
+    /// Diagonal steps are only taken if both orthogonal tiles next to them are reachable,
+    /// so entities do not cut the corners of buildings.
+    pub fn build(map: &Map, terrain: &TerrainRegistry, goal: IVec2) -> Self {
+        let mut chunks: HashMap<IVec2, FlowFieldChunk> = map
+            .loaded_chunks()
+            .map(|chunk_pos| {
+                let chunk = FlowFieldChunk {
+                    integration: [[f32::INFINITY; CHUNK_SIZE]; CHUNK_SIZE],
+                    directions: [[IVec2::ZERO; CHUNK_SIZE]; CHUNK_SIZE],
+                };
+                (chunk_pos, chunk)
+            })
+            .collect();
+        let integration = |chunks: &HashMap<IVec2, FlowFieldChunk>, tile: IVec2| {
+            let (chunk_pos, local_pos) = Map::global_to_chunk(tile);
+            chunks.get(&chunk_pos).map_or(f32::INFINITY, |chunk| {
+                chunk.integration[local_pos.x as usize][local_pos.y as usize]
+            })
+        };
+        let set_integration =
+            |chunks: &mut HashMap<IVec2, FlowFieldChunk>, tile: IVec2, cost: f32| {
+                let (chunk_pos, local_pos) = Map::global_to_chunk(tile);
+                if let Some(chunk) = chunks.get_mut(&chunk_pos) {
+                    chunk.integration[local_pos.x as usize][local_pos.y as usize] = cost;
+                }
+            };
+
+        if movement_cost(map, terrain, goal).is_some() {
+            set_integration(&mut chunks, goal, 0.0);
+            let mut open = BinaryHeap::from([OpenTile {
+                estimate: 0.0,
+                tile: goal,
+            }]);
+            while let Some(OpenTile { estimate, tile }) = open.pop() {
+                if estimate > integration(&chunks, tile) {
+                    // already reached more cheaply
+                    continue;
+                }
+                // entering `tile` from a neighbor costs the movement cost of `tile`
+                let Some(step_cost) = movement_cost(map, terrain, tile) else {
+                    continue;
+                };
+                for direction in &DIRECTIONS[..4] {
+                    let neighbor = tile + direction;
+                    let cost = estimate + step_cost;
+                    if movement_cost(map, terrain, neighbor).is_none()
+                        || cost >= integration(&chunks, neighbor)
+                    {
+                        continue;
+                    }
+                    set_integration(&mut chunks, neighbor, cost);
+                    open.push(OpenTile {
+                        estimate: cost,
+                        tile: neighbor,
+                    });
+                }
+            }
+        }
+
+        let chunk_positions: Vec<IVec2> = chunks.keys().copied().collect();
+        for chunk_pos in chunk_positions {
+            for x in 0..CHUNK_SIZE {
+                for y in 0..CHUNK_SIZE {
+                    let tile = Map::chunk_to_global(chunk_pos, IVec2::new(x as i32, y as i32));
+                    if tile == goal {
+                        continue;
+                    }
+                    let mut best = (f32::INFINITY, IVec2::ZERO);
+                    for &direction in &DIRECTIONS {
+                        let diagonal = direction.x != 0 && direction.y != 0;
+                        if diagonal
+                            && (integration(&chunks, tile + direction.with_y(0)).is_infinite()
+                                || integration(&chunks, tile + direction.with_x(0)).is_infinite())
+                        {
+                            continue;
+                        }
+                        let neighbor = tile + direction;
+                        // the integration of a tile does not include entering the tile itself
+                        let Some(step_cost) = movement_cost(map, terrain, neighbor) else {
+                            continue;
+                        };
+                        let cost = step_cost + integration(&chunks, neighbor);
+                        if cost < best.0 {
+                            best = (cost, direction);
+                        }
+                    }
+                    chunks.get_mut(&chunk_pos).unwrap().directions[x][y] = best.1;
+                }
+            }
+        }
+
+        Self {
+            revision: map.navigation_revision(),
+            chunks,
+        }
+    }
+
+    /// Summed movement cost from the tile at `global_pos` to the goal,
+    /// or `None` if the goal cannot be reached from there.
+    pub fn cost(&self, global_pos: IVec2) -> Option<f32> {
+        let (chunk_pos, local_pos) = Map::global_to_chunk(global_pos);
+        let cost =
+            self.chunks.get(&chunk_pos)?.integration[local_pos.x as usize][local_pos.y as usize];
+        cost.is_finite().then_some(cost)
+    }
+
+    /// Step to take from the tile at `global_pos` towards the goal, one of the eight
+    /// neighboring tiles. Returns `None` at the goal and where the goal cannot be reached.
+    pub fn direction(&self, global_pos: IVec2) -> Option<IVec2> {
+        let (chunk_pos, local_pos) = Map::global_to_chunk(global_pos);
+        let direction =
+            self.chunks.get(&chunk_pos)?.directions[local_pos.x as usize][local_pos.y as usize];
+        (direction != IVec2::ZERO).then_some(direction)
+    }
+}
+
+/// Tile an entity moves towards by following the shared [`FlowField`] of that goal.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct FlowFieldTarget {
+    pub goal: IVec2,
+}
+
+/// Flow fields of all goals some [`FlowFieldTarget`] is heading to.
+#[derive(Resource, Default)]
+pub struct FlowFields {
+    fields: HashMap<IVec2, FlowField>,
+}
+
+impl FlowFields {
+    pub fn get(&self, goal: IVec2) -> Option<&FlowField> {
+        self.fields.get(&goal)
+    }
+}
+
+/// Builds flow fields for new goals, drops fields nobody heads to anymore,
+/// and rebuilds fields that are outdated because the map or the terrain changed.
+fn update_flow_fields(
+    map: Res<Map>,
+    terrain: Res<TerrainRegistry>,
+    mut flow_fields: ResMut<FlowFields>,
+    targets: Query<&FlowFieldTarget>,
+) {
+    let goals: HashSet<IVec2> = targets.iter().map(|target| target.goal).collect();
+    flow_fields.fields.retain(|goal, field| {
+        goals.contains(goal) && field.revision == map.navigation_revision() && !terrain.is_changed()
+    });
+    for goal in goals {
+        flow_fields.fields.entry(goal).or_insert_with(|| {
+            debug!("Building flow field towards {}", goal);
+            FlowField::build(&map, &terrain, goal)
+        });
+    }
+}
+
+/// Draws an arrow on every visible tile pointing along each flow field.
+fn debug_flow_fields(
+    mut gizmos: Gizmos,
+    flow_fields: Res<FlowFields>,
+    camera_query: Single<(&Camera, &GlobalTransform), With<PlayerCamera>>,
+) {
+    let (camera, camera_transform) = camera_query.into_inner();
+    let Some(visible) = visible_chunks(camera, camera_transform) else {
+        return;
+    };
+    let color = Color::srgba(0.9, 0.3, 1.0, 0.6);
+    for field in flow_fields.fields.values() {
+        for chunk_pos in field.chunks.keys().filter(|&&pos| visible.contains(pos)) {
+            for x in 0..CHUNK_SIZE {
+                for y in 0..CHUNK_SIZE {
+                    let tile = Map::chunk_to_global(*chunk_pos, IVec2::new(x as i32, y as i32));
+                    let Some(direction) = field.direction(tile) else {
+                        continue;
+                    };
+                    let center = Map::tile_center(tile);
+                    gizmos
+                        .arrow_2d(
+                            center - direction.as_vec2() * FIELD_SIZE * 0.3,
+                            center + direction.as_vec2() * FIELD_SIZE * 0.3,
+                            color,
+                        )
+                        .with_tip_length(FIELD_SIZE * 0.2);
+                }
+            }
+        }
+    }
+}
+
+pub struct FlowFieldPlugin;
+
+impl Plugin for FlowFieldPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FlowFields>().add_systems(
+            Update,
+            (
+                update_flow_fields,
+                debug_flow_fields.run_if(|overlays: Res<DebugOverlays>| overlays.pathfinding),
+            )
+                .chain()
+                .run_if(in_state(AppState::Game)),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        map::CHUNK_SIZE_I32,
+        pathfinding::tests::{test_map, test_terrain},
+    };
+
+    fn grass(_: IVec2) -> &'static str {
+        "test:grass"
+    }
+
+    /// Follows the directions of `field` from `start`, returning the tiles visited.
+    fn follow(field: &FlowField, start: IVec2) -> Vec<IVec2> {
+        let mut tiles = vec![start];
+        while let Some(direction) = field.direction(*tiles.last().unwrap()) {
+            let next = *tiles.last().unwrap() + direction;
+            assert!(!tiles.contains(&next), "directions loop at {}", next);
+            tiles.push(next);
+        }
+        tiles
+    }
+
+    #[test]
+    fn directions_lead_around_occupied_tiles() {
+        let terrain = test_terrain(1.0);
+        let wall: Vec<IVec2> = (0..12).map(|y| IVec2::new(5, y)).collect();
+        let map = test_map(&terrain, &[IVec2::ZERO], grass, &wall);
+        let goal = IVec2::new(8, 2);
+        let field = FlowField::build(&map, &terrain, goal);
+
+        let tiles = follow(&field, IVec2::new(2, 2));
+        assert_eq!(tiles.last(), Some(&goal));
+        assert!(tiles.iter().all(|tile| !wall.contains(tile)));
+        for wall_tile in &wall {
+            assert_eq!(field.cost(*wall_tile), None);
+        }
+    }
+
+    #[test]
+    fn costs_respect_terrain() {
+        let terrain = test_terrain(4.0);
+        let goal = IVec2::new(2, 2);
+        let mud = |tile: IVec2| {
+            if tile == IVec2::new(3, 2) {
+                "test:mud"
+            } else {
+                "test:grass"
+            }
+        };
+        let map = test_map(&terrain, &[IVec2::ZERO], mud, &[]);
+        let field = FlowField::build(&map, &terrain, goal);
+
+        assert_eq!(field.cost(goal), Some(0.0));
+        assert_eq!(field.cost(goal + IVec2::new(0, 3)), Some(3.0));
+        // leaving the mud only costs the tile entered next
+        assert_eq!(field.cost(IVec2::new(3, 2)), Some(1.0));
+        // entering the mud costs 4, walking around it via the next row costs 4 as well
+        assert_eq!(field.cost(IVec2::new(4, 2)), Some(4.0));
+        assert_ne!(field.direction(IVec2::new(4, 2)), Some(IVec2::NEG_X));
+    }
+
+    #[test]
+    fn fields_cross_chunk_boundaries() {
+        let terrain = test_terrain(1.0);
+        let map = test_map(&terrain, &[IVec2::ZERO, IVec2::X], grass, &[]);
+        let goal = IVec2::new(CHUNK_SIZE_I32 + 4, 2);
+        let field = FlowField::build(&map, &terrain, goal);
+
+        let start = IVec2::new(1, 2);
+        assert_eq!(field.cost(start), Some((goal - start).x as f32));
+        assert_eq!(follow(&field, start).last(), Some(&goal));
+        // tiles of chunks that are not loaded have no direction
+        assert_eq!(field.direction(IVec2::new(-1, 2)), None);
+    }
+
+    #[test]
+    fn unreachable_tiles_have_no_direction() {
+        let terrain = test_terrain(1.0);
+        let enclosed = IVec2::new(8, 8);
+        let enclosure =
+            [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y].map(|offset| enclosed + offset);
+        let map = test_map(&terrain, &[IVec2::ZERO], grass, &enclosure);
+
+        let field = FlowField::build(&map, &terrain, IVec2::new(2, 2));
+        assert_eq!(field.cost(enclosed), None);
+        assert_eq!(field.direction(enclosed), None);
+
+        // nothing can reach an occupied goal
+        let field = FlowField::build(&map, &terrain, enclosure[0]);
+        assert_eq!(field.cost(IVec2::new(2, 2)), None);
+        assert_eq!(field.direction(IVec2::new(2, 2)), None);
+    }
+
+    #[test]
+    fn directions_do_not_cut_corners() {
+        let terrain = test_terrain(1.0);
+        let goal = IVec2::new(6, 6);
+        let blocked = [IVec2::new(5, 6), IVec2::new(9, 9), IVec2::new(10, 8)];
+        let map = test_map(&terrain, &[IVec2::ZERO], grass, &blocked);
+        let field = FlowField::build(&map, &terrain, goal);
+
+        // the diagonal step to the goal would brush past the building at (5, 6)
+        assert_eq!(field.direction(IVec2::new(5, 5)), Some(IVec2::X));
+        for x in 0..CHUNK_SIZE_I32 {
+            for y in 0..CHUNK_SIZE_I32 {
+                let tile = IVec2::new(x, y);
+                let Some(direction) = field.direction(tile) else {
+                    continue;
+                };
+                if direction.x != 0 && direction.y != 0 {
+                    assert!(
+                        field.cost(tile + direction.with_y(0)).is_some()
+                            && field.cost(tile + direction.with_x(0)).is_some(),
+                        "{} cuts a corner towards {}",
+                        tile,
+                        direction
+                    );
+                }
+            }
+        }
+    }
+}
@@ -33,3 +33,43 @@ pub fn create_polygon_mesh(sides: usize, radius: f32) -> Mesh {
     .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, vertices)
     .with_inserted_indices(Indices::U32(indices))
 }
+
+/// Creates a grid of `columns` × `rows` square tiles with a side length of `tile_size`,
+/// starting at the origin and extending into positive x and y.
+/// Each tile is a separate quad colored with `tile_color(column, row)` through vertex colors.
+pub fn create_tile_grid_mesh(
+    columns: usize,
+    rows: usize,
+    tile_size: f32,
+    tile_color: impl Fn(usize, usize) -> Color,
+) -> Mesh {
+    let tiles = columns * rows;
+    let mut vertices = Vec::with_capacity(tiles * 4);
+    let mut colors = Vec::with_capacity(tiles * 4);
+    let mut indices = Vec::with_capacity(tiles * 6);
+
+    for column in 0..columns {
+        for row in 0..rows {
+            let first = vertices.len() as u32;
+            let x = column as f32 * tile_size;
+            let y = row as f32 * tile_size;
+            vertices.push([x, y, 0.0]);
+            vertices.push([x + tile_size, y, 0.0]);
+            vertices.push([x + tile_size, y + tile_size, 0.0]);
+            vertices.push([x, y + tile_size, 0.0]);
+
+            let color = tile_color(column, row).to_linear().to_f32_array();
+            colors.extend([color; 4]);
+
+            indices.extend([first, first + 1, first + 2, first, first + 2, first + 3]);
+        }
+    }
+
+    Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+    )
+    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, vertices)
+    .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, colors)
+    .with_inserted_indices(Indices::U32(indices))
+}
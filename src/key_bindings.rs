@@ -0,0 +1,59 @@
+//! Keys bound to the controls of the game.
+//!
+//! Systems handling a control read its key from here, so building hotkeys from content
+//! modules can be checked against [`reserved_keys`].
+
+use bevy::prelude::*;
+
+pub const CAMERA_UP: KeyCode = KeyCode::KeyW;
+pub const CAMERA_DOWN: KeyCode = KeyCode::KeyS;
+pub const CAMERA_LEFT: KeyCode = KeyCode::KeyA;
+pub const CAMERA_RIGHT: KeyCode = KeyCode::KeyD;
+/// Turns the building placed in build mode.
+pub const ROTATE_BUILDING: KeyCode = KeyCode::KeyR;
+/// Demolishes the building under the mouse cursor.
+pub const DEMOLISH: KeyCode = KeyCode::KeyX;
+/// Leaves build mode and cancels the command waiting for a target.
+pub const CANCEL: KeyCode = KeyCode::Escape;
+/// Keys of the control groups, the index of a key is the index of its group.
+pub const CONTROL_GROUPS: [KeyCode; 10] = [
+    KeyCode::Digit0,
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+];
+pub const CHUNK_BOUNDS_OVERLAY: KeyCode = KeyCode::F1;
+pub const OCCUPANCY_OVERLAY: KeyCode = KeyCode::F2;
+pub const TERRAIN_OVERLAY: KeyCode = KeyCode::F3;
+pub const PATHFINDING_OVERLAY: KeyCode = KeyCode::F4;
+pub const ENTITY_IDS_OVERLAY: KeyCode = KeyCode::F5;
+pub const QUICKSAVE: KeyCode = KeyCode::F6;
+pub const QUICKLOAD: KeyCode = KeyCode::F9;
+
+/// Every key bound above, which building hotkeys must not use.
+pub fn reserved_keys() -> impl Iterator<Item = KeyCode> {
+    [
+        CAMERA_UP,
+        CAMERA_DOWN,
+        CAMERA_LEFT,
+        CAMERA_RIGHT,
+        ROTATE_BUILDING,
+        DEMOLISH,
+        CANCEL,
+        CHUNK_BOUNDS_OVERLAY,
+        OCCUPANCY_OVERLAY,
+        TERRAIN_OVERLAY,
+        PATHFINDING_OVERLAY,
+        ENTITY_IDS_OVERLAY,
+        QUICKSAVE,
+        QUICKLOAD,
+    ]
+    .into_iter()
+    .chain(CONTROL_GROUPS)
+}
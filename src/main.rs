@@ -1,55 +1,187 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, hash_map::Entry};
 
 use bevy::{prelude::*, window::PrimaryWindow};
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    graphics::create_polygon_mesh,
-    map::{
-        CHUNK_HALF_SIZE, CHUNK_SIZE, CHUNK_SIZE_F32, CHUNK_SIZE_I32, ChunkEntity, FIELD_SIZE, Map,
-    },
+    build_mode::BuildModePlugin,
+    building_definitions::{BuildingComponentRegistry, BuildingDefinitionsPlugin},
+    chunk_mesh::ChunkMeshPlugin,
+    chunk_streaming::ChunkStreamingPlugin,
+    control_groups::ControlGroupsPlugin,
+    debug_overlay::DebugOverlayPlugin,
+    flow_field::FlowFieldPlugin,
+    map::{FIELD_SIZE, Map, PlacementError},
+    module_loader::ModuleLoaderPlugin,
+    pathfinding::PathfindingPlugin,
     player_camera::{PlayerCamera, PlayerCameraPlugin},
+    production::{PlayerResources, ProductionPlugin, ProductionQueue},
+    save::SavePlugin,
+    selection::SelectionPlugin,
+    terrain::{TerrainPlugin, TerrainRegistry},
     toasts::{ToastMessage, ToastsPlugin},
+    unit_definitions::UnitDefinitionsPlugin,
+    units::UnitsPlugin,
     user_controls::UserControlsPlugin,
 };
 
+mod build_mode;
+mod building_definitions;
+mod chunk_mesh;
+mod chunk_streaming;
+mod control_groups;
+mod debug_overlay;
+mod flow_field;
 mod graphics;
+mod key_bindings;
 mod map;
 mod module_loader;
+mod pathfinding;
 mod player_camera;
+mod production;
+mod save;
+mod selection;
+mod terrain;
 mod toasts;
+mod unit_definitions;
+mod units;
 mod user_controls;
 
+/// Rotation of a building in counterclockwise 90° steps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+enum BuildingRotation {
+    #[default]
+    Deg0,
+    Deg90,
+    Deg180,
+    Deg270,
+}
+
+impl BuildingRotation {
+    /// Returns the rotation turned by another 90° counterclockwise.
+    fn next(self) -> Self {
+        match self {
+            Self::Deg0 => Self::Deg90,
+            Self::Deg90 => Self::Deg180,
+            Self::Deg180 => Self::Deg270,
+            Self::Deg270 => Self::Deg0,
+        }
+    }
+
+    /// Rotates a tile offset around the origin tile.
+    fn rotate_offset(self, offset: IVec2) -> IVec2 {
+        match self {
+            Self::Deg0 => offset,
+            Self::Deg90 => IVec2::new(-offset.y, offset.x),
+            Self::Deg180 => -offset,
+            Self::Deg270 => IVec2::new(offset.y, -offset.x),
+        }
+    }
+
+    /// Rotates a world space vector.
+    fn rotate_vec2(self, vector: Vec2) -> Vec2 {
+        match self {
+            Self::Deg0 => vector,
+            Self::Deg90 => Vec2::new(-vector.y, vector.x),
+            Self::Deg180 => -vector,
+            Self::Deg270 => Vec2::new(vector.y, -vector.x),
+        }
+    }
+
+    fn to_quat(self) -> Quat {
+        let quarter_turns = match self {
+            Self::Deg0 => 0.0,
+            Self::Deg90 => 1.0,
+            Self::Deg180 => 2.0,
+            Self::Deg270 => 3.0,
+        };
+        Quat::from_rotation_z(quarter_turns * std::f32::consts::FRAC_PI_2)
+    }
+}
+
 /// Trait for building construction logic.
 trait BuildingBuilder: Send + Sync + 'static {
-    fn build(&self, entry: &BuildingEntry, commands: &mut Commands, position: IVec2);
+    /// Spawns the building with its footprint origin at the global tile `position`
+    /// and returns the spawned entity.
+    fn build(
+        &self,
+        entry: &BuildingEntry,
+        commands: &mut Commands,
+        position: IVec2,
+        rotation: BuildingRotation,
+    ) -> Entity;
 }
 
 impl<F> BuildingBuilder for F
 where
-    F: Fn(&BuildingEntry, &mut Commands, IVec2) + Send + Sync + 'static,
+    F: Fn(&BuildingEntry, &mut Commands, IVec2, BuildingRotation) -> Entity + Send + Sync + 'static,
 {
-    fn build(&self, entry: &BuildingEntry, commands: &mut Commands, position: IVec2) {
-        (self)(entry, commands, position)
+    fn build(
+        &self,
+        entry: &BuildingEntry,
+        commands: &mut Commands,
+        position: IVec2,
+        rotation: BuildingRotation,
+    ) -> Entity {
+        (self)(entry, commands, position, rotation)
     }
 }
 
 struct BuildingEntry {
     occlusion_map: Vec<IVec2>,
+    /// Key that selects this building for placement in [`InputMode::Normal`] and [`InputMode::Build`].
+    hotkey: Option<KeyCode>,
     build_cursor_offset: Vec2,
     mesh_handle: Handle<Mesh>,
     material_handle: Handle<ColorMaterial>,
     description: Option<String>,
+    cost: u32,
+    /// Construction time in seconds.
+    build_time: f32,
     builder: Box<dyn BuildingBuilder>,
 }
 
+impl BuildingEntry {
+    /// Occlusion map turned by `rotation` around the origin tile.
+    fn rotated_occlusion_map(&self, rotation: BuildingRotation) -> Vec<IVec2> {
+        self.occlusion_map
+            .iter()
+            .map(|&offset| rotation.rotate_offset(offset))
+            .collect()
+    }
+
+    /// Global tile position of the footprint origin when the build cursor is at `world_pos`.
+    fn origin_for_cursor(&self, world_pos: Vec2, rotation: BuildingRotation) -> IVec2 {
+        ((world_pos + rotation.rotate_vec2(self.build_cursor_offset)) / FIELD_SIZE)
+            .floor()
+            .as_ivec2()
+    }
+
+    /// World position of the building whose footprint origin is at the global tile `origin`.
+    /// This is the inverse of [`BuildingEntry::origin_for_cursor`] for a centered cursor.
+    fn world_position(&self, origin: IVec2, rotation: BuildingRotation) -> Vec2 {
+        origin.as_vec2() * FIELD_SIZE + Vec2::splat(FIELD_SIZE / 2.0)
+            - rotation.rotate_vec2(self.build_cursor_offset)
+    }
+
+    /// Transform of the building whose footprint origin is at the global tile `origin`.
+    fn transform(&self, origin: IVec2, rotation: BuildingRotation) -> Transform {
+        Transform::from_translation(self.world_position(origin, rotation).extend(0.0))
+            .with_rotation(rotation.to_quat())
+    }
+}
+
 impl std::fmt::Debug for BuildingEntry {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("BuildingEntry")
             .field("occlusion_map", &self.occlusion_map)
+            .field("hotkey", &self.hotkey)
             .field("build_cursor_offset", &self.build_cursor_offset)
             .field("mesh_handle", &self.mesh_handle)
             .field("material_handle", &self.material_handle)
             .field("description", &self.description)
+            .field("cost", &self.cost)
+            .field("build_time", &self.build_time)
             .finish()
     }
 }
@@ -60,83 +192,187 @@ struct BuildingRegistry {
 }
 
 impl BuildingRegistry {
-    fn register(&mut self, id: impl Into<String>, entry: BuildingEntry) {
+    /// Registers a new building.
+    /// Fails if a building with the same id is already registered;
+    /// use [`BuildingRegistry::replace`] to override it explicitly.
+    fn register(&mut self, id: impl Into<String>, entry: BuildingEntry) -> Result<(), String> {
         let id = id.into();
-        info!("Registering building: {} -> {:?}", id, entry);
-        self.buildings.insert(id, entry);
+        match self.buildings.entry(id) {
+            Entry::Vacant(e) => {
+                info!("Registering building: {} -> {:?}", e.key(), entry);
+                e.insert(entry);
+                Ok(())
+            }
+            Entry::Occupied(e) => Err(format!("building '{}' is already registered", e.key())),
+        }
+    }
+
+    /// Replaces an already registered building and returns the previous entry.
+    /// Returns `None` and registers nothing if no building with that id exists.
+    fn replace(&mut self, id: &str, entry: BuildingEntry) -> Option<BuildingEntry> {
+        let existing = self.buildings.get_mut(id)?;
+        info!("Replacing building: {} -> {:?}", id, entry);
+        Some(std::mem::replace(existing, entry))
+    }
+
+    /// Id of a building other than `except` that is selected with `hotkey`.
+    fn building_with_hotkey(&self, hotkey: KeyCode, except: &str) -> Option<&str> {
+        self.buildings
+            .iter()
+            .find(|(id, entry)| *id != except && entry.hotkey == Some(hotkey))
+            .map(|(id, _)| id.as_str())
+    }
+
+    /// Spawns the building `id` with its footprint origin at the global tile `origin`
+    /// and occupies its footprint, turned by `rotation`, on the map.
+    ///
+    /// Nothing is spawned if the footprint cannot be placed.
+    fn place(
+        &self,
+        id: &str,
+        origin: IVec2,
+        rotation: BuildingRotation,
+        map: &mut Map,
+        terrain: &TerrainRegistry,
+        commands: &mut Commands,
+    ) -> Result<Entity, PlaceBuildingError> {
+        let entry = self
+            .buildings
+            .get(id)
+            .ok_or_else(|| PlaceBuildingError::UnknownBuilding { id: id.to_string() })?;
+        let occlusion_map = entry.rotated_occlusion_map(rotation);
+        if let Err(errors) = map.can_place(origin, &occlusion_map, terrain) {
+            return Err(errors
+                .into_iter()
+                .next()
+                .expect("can_place reports at least one error")
+                .into());
+        }
+        let entity = entry.builder.build(entry, commands, origin, rotation);
+        map.try_place(origin, &occlusion_map, entity, terrain)
+            .expect("Placement must succeed here; we checked before");
+        commands.entity(entity).insert(PlacedBuilding {
+            id: id.to_string(),
+            origin,
+            rotation,
+        });
+        Ok(entity)
     }
 }
 
-#[derive(Component)]
-struct Barracks;
-const BARRACKS_ID: &str = "core:barracks";
+/// Reason why [`BuildingRegistry::place`] could not place a building.
+#[derive(Debug)]
+enum PlaceBuildingError {
+    /// No building with the id is registered.
+    UnknownBuilding { id: String },
+    /// The footprint of the building cannot be placed on the map.
+    Placement(PlacementError),
+}
 
-fn setup_buildings(
-    mut registry: ResMut<BuildingRegistry>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-) {
-    let barracks_mesh = create_polygon_mesh(5, FIELD_SIZE);
-    let barracks_mesh_handle = meshes.add(barracks_mesh);
-    let barracks_material = ColorMaterial::from_color(Color::srgb(0.6, 0.2, 0.2));
-    let barracks_material_handle = materials.add(barracks_material);
-    let barracks_builder = Box::new(
-        |entry: &BuildingEntry, commands: &mut Commands, position: IVec2| {
-            commands.spawn((
-                Barracks,
-                Transform::from_translation(Vec3::new(
-                    position.x as f32 * FIELD_SIZE + FIELD_SIZE / 2.0,
-                    position.y as f32 * FIELD_SIZE + FIELD_SIZE / 2.0,
-                    0.0,
-                )),
-                GlobalTransform::default(),
-                Mesh2d(entry.mesh_handle.clone()),
-                MeshMaterial2d(entry.material_handle.clone()),
-            ));
-        },
-    );
-    let barracks_entry = BuildingEntry {
-        occlusion_map: vec![
-            IVec2::new(0, 0),
-            IVec2::new(1, 0),
-            IVec2::new(0, 1),
-            IVec2::new(1, 1),
-        ],
-        // offset in top left direction to center the build cursor
-        build_cursor_offset: Vec2::splat(-FIELD_SIZE / 2.0),
-        mesh_handle: barracks_mesh_handle,
-        material_handle: barracks_material_handle,
-        description: Some("Used to train infantry units.".to_string()),
-        builder: barracks_builder,
-    };
-    registry.register(BARRACKS_ID, barracks_entry);
+impl std::fmt::Display for PlaceBuildingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownBuilding { id } => write!(f, "unknown building '{}'", id),
+            Self::Placement(error) => write!(f, "{}", error),
+        }
+    }
 }
 
-fn setup_map(
-    mut commands: Commands,
+impl std::error::Error for PlaceBuildingError {}
+
+impl From<PlacementError> for PlaceBuildingError {
+    fn from(error: PlacementError) -> Self {
+        Self::Placement(error)
+    }
+}
+
+/// Marks an entity as a building placed on the [`Map`].
+#[derive(Component, Debug, Clone)]
+struct PlacedBuilding {
+    /// Registry id of the building.
+    id: String,
+    /// Global tile position of the footprint origin.
+    origin: IVec2,
+    /// Rotation of the footprint and mesh around the origin tile.
+    rotation: BuildingRotation,
+}
+
+/// Sent after the [`BuildingRegistry`] entry of a building was replaced,
+/// e.g. by a reloaded definition file.
+#[derive(Message, Debug, Clone)]
+struct BuildingEntryReplaced {
+    id: String,
+}
+
+type PlacedBuildingData<'a> = (
+    Entity,
+    &'a PlacedBuilding,
+    &'a mut Transform,
+    &'a mut Mesh2d,
+    &'a mut MeshMaterial2d<ColorMaterial>,
+);
+
+/// Updates placed buildings whose registry entry was replaced.
+///
+/// Footprint, mesh, material and position follow the new entry if the new footprint fits
+/// on the map, otherwise the building keeps its old look and tiles.
+fn refresh_placed_buildings(
+    mut replaced: MessageReader<BuildingEntryReplaced>,
+    registry: Res<BuildingRegistry>,
     mut map: ResMut<Map>,
+    terrain: Res<TerrainRegistry>,
+    mut buildings: Query<PlacedBuildingData>,
     mut toasts: MessageWriter<ToastMessage>,
 ) {
-    for x in -2..2 {
-        for y in -2..2 {
-            map.create_chunk(IVec2::new(x, y), &mut commands);
-            let success = map.try_place(
-                IVec2::new(x * CHUNK_SIZE_I32 + 2, y * CHUNK_SIZE_I32 + 2),
-                &[IVec2::new(0, 0)],
-            );
-            assert!(success, "Placement should succeed here");
+    let ids: HashSet<String> = replaced.read().map(|event| event.id.clone()).collect();
+    for id in ids {
+        let Some(entry) = registry.buildings.get(&id) else {
+            continue;
+        };
+        let mut updated = 0;
+        let mut blocked = 0;
+        for (entity, placed, mut transform, mut mesh, mut material) in &mut buildings {
+            if placed.id != id {
+                continue;
+            }
+            let occlusion_map = entry.rotated_occlusion_map(placed.rotation);
+            if map
+                .replace_footprint(entity, placed.origin, &occlusion_map, &terrain)
+                .is_err()
+            {
+                blocked += 1;
+                continue;
+            }
+            let z = transform.translation.z;
+            *transform = entry.transform(placed.origin, placed.rotation);
+            transform.translation.z = z;
+            mesh.0 = entry.mesh_handle.clone();
+            material.0 = entry.material_handle.clone();
+            updated += 1;
+        }
+        if updated + blocked > 0 {
             toasts.write(ToastMessage {
-                content: format!("Loaded chunk at {}", IVec2::new(x, y)),
+                content: if blocked == 0 {
+                    format!("Updated {} placed '{}' buildings", updated, id)
+                } else {
+                    format!(
+                        "Updated {} placed '{}' buildings, {} were left unchanged because their new footprint is blocked",
+                        updated, id, blocked
+                    )
+                },
             });
         }
     }
-
-    commands.spawn(());
 }
 
-#[derive(Resource, Default)]
-struct CursorBuilding {
-    building_id: Option<String>,
+/// Marker for buildings that train infantry.
+#[derive(Component)]
+struct Barracks;
+
+fn setup_building_components(mut components: ResMut<BuildingComponentRegistry>) {
+    components.register("core:barracks", |entity| {
+        entity.insert((Barracks, ProductionQueue::default()));
+    });
 }
 
 struct MouseCursorPosition {
@@ -188,93 +424,28 @@ fn update_cursor_position(
 }
 
 fn player_controls(
+    mut commands: Commands,
     keyboard_input: Res<ButtonInput<KeyCode>>,
     mut map: ResMut<Map>,
-    registry: Res<BuildingRegistry>,
     mut toasts: MessageWriter<ToastMessage>,
     cursor: Res<MouseCursor>,
+    mut resources: ResMut<PlayerResources>,
+    queues: Query<&ProductionQueue>,
 ) {
-    if keyboard_input.just_pressed(KeyCode::KeyB) {
-        if let Some((chunk_pos, local_pos)) = cursor.grid_position()
-            && let Some(entry) = registry.buildings.get(BARRACKS_ID)
-        {
-            let global_pos = Map::chunk_to_global(chunk_pos, local_pos);
-            let success = map.try_place(global_pos, &entry.occlusion_map);
-            if success {
-                toasts.write(ToastMessage {
-                    content: format!("Placed Barracks at {}", global_pos),
-                });
-            } else {
-                toasts.write(ToastMessage {
-                    content: format!(
-                        "Failed to place Barracks at {}: Space occupied or chunk not loaded",
-                        global_pos
-                    ),
-                });
-            }
-        }
-    }
-}
-
-fn debug_chunk_bounds(mut gizmos: Gizmos, query: Query<&ChunkEntity>) {
-    for chunk in query {
-        let chunk_world_pos =
-            chunk.position().as_vec2() * CHUNK_SIZE_F32 * FIELD_SIZE + CHUNK_HALF_SIZE;
-        gizmos
-            .grid_2d(
-                Isometry2d::from_translation(chunk_world_pos),
-                UVec2::splat(1),
-                Vec2::splat(CHUNK_SIZE_F32 * FIELD_SIZE),
-                Color::srgba(0.0, 0.7, 0.0, 0.5),
-            )
-            .outer_edges();
-    }
-}
-
-fn debug_chunk_fields(
-    mut gizmos: Gizmos,
-    query: Query<&ChunkEntity>,
-    map: Res<Map>,
-    cursor: Res<MouseCursor>,
-) {
-    let color_occupied = Color::srgba(0.7, 0.0, 0.0, 0.4);
-    let color_free = Color::srgba(0.0, 0.7, 0.0, 0.2);
-    let color_hover_occupied = Color::srgba(1.0, 0.3, 0.0, 0.6);
-    let color_hover_free = Color::srgba(0.0, 0.3, 1.0, 0.6);
-    for chunk in query {
-        let chunk_world_pos =
-            chunk.position().as_vec2() * CHUNK_SIZE_F32 * FIELD_SIZE + CHUNK_HALF_SIZE;
-        for x in 0..CHUNK_SIZE {
-            for y in 0..CHUNK_SIZE {
-                let field_pos =
-                    chunk_world_pos + Vec2::new(x as f32 * FIELD_SIZE, y as f32 * FIELD_SIZE);
-                let color = if map.is_occupied(chunk.position(), IVec2::new(x as i32, y as i32)) {
-                    if let Some((cursor_chunk_pos, cursor_local_pos)) = cursor.grid_position()
-                        && cursor_chunk_pos == chunk.position()
-                        && cursor_local_pos == IVec2::new(x as i32, y as i32)
-                    {
-                        color_hover_occupied
-                    } else {
-                        color_occupied
-                    }
-                } else {
-                    if let Some((cursor_chunk_pos, cursor_local_pos)) = cursor.grid_position()
-                        && cursor_chunk_pos == chunk.position()
-                        && cursor_local_pos == IVec2::new(x as i32, y as i32)
-                    {
-                        color_hover_free
-                    } else {
-                        color_free
-                    }
-                };
-                gizmos.circle_2d(
-                    Isometry2d::from_translation(
-                        field_pos + Vec2::splat(FIELD_SIZE / 2.0) - CHUNK_HALF_SIZE,
-                    ),
-                    FIELD_SIZE / 4.0,
-                    color,
-                );
+    if keyboard_input.just_pressed(key_bindings::DEMOLISH)
+        && let Some((chunk_pos, local_pos)) = cursor.grid_position()
+    {
+        let global_pos = Map::chunk_to_global(chunk_pos, local_pos);
+        if let Some(owner) = map.occupant_at(global_pos) {
+            // units still queued in the building are refunded like canceled ones
+            if let Ok(queue) = queues.get(owner) {
+                resources.credits += queue.items.iter().map(|item| item.cost).sum::<u32>();
             }
+            map.remove(owner);
+            commands.entity(owner).despawn();
+            toasts.write(ToastMessage {
+                content: format!("Demolished building at {}", global_pos),
+            });
         }
     }
 }
@@ -285,6 +456,20 @@ pub enum AppState {
     Game,
 }
 
+/// Exclusive mode of mouse and keyboard input while in [`AppState::Game`].
+/// Systems reacting to player input run only in the mode they belong to,
+/// so e.g. a left click cannot both place a building and do something else.
+#[derive(SubStates, Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+#[source(AppState = AppState::Game)]
+pub enum InputMode {
+    #[default]
+    Normal,
+    /// Placing the [`build_mode::CursorBuilding`].
+    Build,
+    /// Selecting the target of a command issued from the control panel.
+    Target,
+}
+
 fn main() {
     App::new()
         .add_plugins((
@@ -295,17 +480,34 @@ fn main() {
         ))
         .init_resource::<Map>()
         .init_resource::<BuildingRegistry>()
-        .init_resource::<CursorBuilding>()
         .init_resource::<MouseCursor>()
+        .add_message::<BuildingEntryReplaced>()
         .init_state::<AppState>()
-        .add_systems(Startup, (setup_map, setup_buildings))
+        .add_sub_state::<InputMode>()
+        .add_plugins((
+            BuildModePlugin,
+            BuildingDefinitionsPlugin,
+            ChunkMeshPlugin,
+            ChunkStreamingPlugin,
+            ControlGroupsPlugin,
+            DebugOverlayPlugin,
+            FlowFieldPlugin,
+            ModuleLoaderPlugin,
+            PathfindingPlugin,
+            ProductionPlugin,
+            SavePlugin,
+            SelectionPlugin,
+            TerrainPlugin,
+            UnitDefinitionsPlugin,
+            UnitsPlugin,
+        ))
+        .add_systems(Startup, setup_building_components)
         .add_systems(
             Update,
             (
-                debug_chunk_bounds,
-                debug_chunk_fields,
-                player_controls,
+                player_controls.run_if(in_state(InputMode::Normal)),
                 update_cursor_position,
+                refresh_placed_buildings,
             ),
         )
         .run();
@@ -1,13 +1,76 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use bevy::prelude::*;
 
+use crate::terrain::{ChunkTerrain, TerrainId, TerrainRegistry};
+
 pub const FIELD_SIZE: f32 = 4.0;
 pub const CHUNK_SIZE: usize = 16;
 pub const CHUNK_SIZE_I32: i32 = CHUNK_SIZE as i32;
 pub const CHUNK_SIZE_F32: f32 = CHUNK_SIZE as f32;
 pub const CHUNK_HALF_SIZE: Vec2 = Vec2::splat(CHUNK_SIZE_F32 * FIELD_SIZE / 2.0);
 
+/// Number of chunks from the world origin to the world border in every direction.
+pub const WORLD_HALF_EXTENT_CHUNKS: i32 = 64;
+
+/// Reason why a tile cannot be occupied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlacementError {
+    /// The tile is already occupied by another entity.
+    Occupied { position: IVec2, occupant: Entity },
+    /// The chunk containing the tile is not loaded.
+    ChunkNotLoaded { position: IVec2, chunk: IVec2 },
+    /// The tile lies outside of the world bounds.
+    OutOfBounds { position: IVec2 },
+    /// The terrain of the tile does not allow building.
+    TerrainDisallowed { position: IVec2 },
+    /// A game rule forbids building on the tile.
+    /// Never reported by the map itself, only by rules checked on top of it.
+    #[allow(dead_code)]
+    RuleViolation { position: IVec2, reason: String },
+}
+
+impl PlacementError {
+    /// Global position of the blocking tile.
+    pub fn position(&self) -> IVec2 {
+        match self {
+            Self::Occupied { position, .. }
+            | Self::ChunkNotLoaded { position, .. }
+            | Self::OutOfBounds { position }
+            | Self::TerrainDisallowed { position }
+            | Self::RuleViolation { position, .. } => *position,
+        }
+    }
+}
+
+impl std::fmt::Display for PlacementError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Occupied { position, occupant } => {
+                write!(f, "tile {} is occupied by {}", position, occupant)
+            }
+            Self::ChunkNotLoaded { position, chunk } => {
+                write!(
+                    f,
+                    "tile {} is in chunk {} which is not loaded",
+                    position, chunk
+                )
+            }
+            Self::OutOfBounds { position } => {
+                write!(f, "tile {} is outside of the world", position)
+            }
+            Self::TerrainDisallowed { position } => {
+                write!(f, "terrain at tile {} does not allow building", position)
+            }
+            Self::RuleViolation { position, reason } => {
+                write!(f, "tile {} cannot be built on: {}", position, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PlacementError {}
+
 #[derive(Component, Debug, Clone, Copy)]
 pub struct ChunkEntity {
     position: IVec2,
@@ -21,24 +84,56 @@ impl ChunkEntity {
 }
 
 struct ChunkData {
-    tiles: [[bool; CHUNK_SIZE]; CHUNK_SIZE],
+    /// Entity occupying each tile, or `None` if the tile is free.
+    tiles: [[Option<Entity>; CHUNK_SIZE]; CHUNK_SIZE],
+    terrain: ChunkTerrain,
 }
 
 impl ChunkData {
-    fn new() -> Self {
+    fn new(terrain: ChunkTerrain) -> Self {
         Self {
-            tiles: [[false; CHUNK_SIZE]; CHUNK_SIZE],
+            tiles: [[None; CHUNK_SIZE]; CHUNK_SIZE],
+            terrain,
         }
     }
 
-    fn set(&mut self, local_pos: IVec2, value: bool) {
-        self.tiles[local_pos.x as usize][local_pos.y as usize] = value;
+    #[inline]
+    fn terrain(&self, local_pos: IVec2) -> TerrainId {
+        self.terrain[local_pos.x as usize][local_pos.y as usize]
+    }
+
+    #[inline]
+    fn get(&self, local_pos: IVec2) -> Option<Entity> {
+        self.tiles[local_pos.x as usize][local_pos.y as usize]
+    }
+
+    fn set(&mut self, local_pos: IVec2, occupant: Option<Entity>) {
+        self.tiles[local_pos.x as usize][local_pos.y as usize] = occupant;
     }
 }
 
 #[derive(Default, Resource)]
 pub struct Map {
+    /// Loaded chunks.
     chunks: HashMap<IVec2, ChunkData>,
+    /// [`ChunkEntity`] of every loaded chunk.
+    chunk_entities: HashMap<IVec2, Entity>,
+    /// State of chunks that were loaded before and unloaded again,
+    /// restored when they are loaded the next time.
+    /// Chunks that can be generated again unchanged are not kept, see [`Map::unload_chunk`].
+    unloaded_chunks: HashMap<IVec2, ChunkData>,
+    /// Loaded chunks whose terrain changed since the last [`Map::take_changed_chunks`].
+    changed_chunks: HashSet<IVec2>,
+    /// Global tile positions that were occupied or freed since the last
+    /// [`Map::take_changed_tiles`].
+    changed_tiles: HashSet<IVec2>,
+    /// Chunks that were loaded, unloaded or restored while loaded since the last
+    /// [`Map::take_streamed_chunks`].
+    streamed_chunks: HashSet<IVec2>,
+    /// Incremented whenever tiles become passable or blocked, or chunks load or unload.
+    navigation_revision: u64,
+    /// Global tile positions occupied by each placed entity.
+    footprints: HashMap<Entity, Vec<IVec2>>,
 }
 
 impl Map {
@@ -71,70 +166,404 @@ impl Map {
         )
     }
 
-    pub fn create_chunk(&mut self, pos: IVec2, commands: &mut Commands) {
-        if self.chunks.insert(pos, ChunkData::new()).is_some() {
-            // for now, we just panic if chunk exists
-            panic!("Chunk at position {:?} already exists!", pos);
+    /// Global tile position containing the given world position.
+    #[inline]
+    pub fn world_to_global(world_pos: Vec2) -> IVec2 {
+        (world_pos / FIELD_SIZE).floor().as_ivec2()
+    }
+
+    /// Center of the tile at the given global position, in world space.
+    #[inline]
+    pub fn tile_center(global_pos: IVec2) -> Vec2 {
+        global_pos.as_vec2() * FIELD_SIZE + Vec2::splat(FIELD_SIZE / 2.0)
+    }
+
+    /// Loads the chunk at `pos` and spawns its [`ChunkEntity`].
+    ///
+    /// A chunk that was unloaded before gets its previous state back, any other chunk
+    /// starts out empty with the terrain returned by `generate`. Does nothing if the chunk
+    /// is already loaded.
+    ///
+    /// # Returns
+    /// - `true` if the chunk was loaded by this call.
+    pub fn load_chunk(
+        &mut self,
+        pos: IVec2,
+        generate: impl FnOnce(IVec2) -> ChunkTerrain,
+        commands: &mut Commands,
+    ) -> bool {
+        if self.chunks.contains_key(&pos) {
+            return false;
         }
-        commands.spawn(ChunkEntity { position: pos });
+        let chunk = self
+            .unloaded_chunks
+            .remove(&pos)
+            .unwrap_or_else(|| ChunkData::new(generate(pos)));
+        self.chunks.insert(pos, chunk);
+        let entity = commands.spawn(ChunkEntity { position: pos }).id();
+        self.chunk_entities.insert(pos, entity);
+        self.changed_chunks.insert(pos);
+        self.streamed_chunks.insert(pos);
+        self.navigation_revision += 1;
+        true
     }
 
-    pub fn try_place(&mut self, pos: IVec2, occlusion_map: &[IVec2]) -> bool {
-        // check occlusion
-        for offset in occlusion_map {
-            let check_pos = pos + offset;
-            let chunk_pos = IVec2::new(
-                check_pos.x.div_euclid(CHUNK_SIZE_I32),
-                check_pos.y.div_euclid(CHUNK_SIZE_I32),
-            );
-            let local_pos = IVec2::new(
-                check_pos.x.rem_euclid(CHUNK_SIZE_I32),
-                check_pos.y.rem_euclid(CHUNK_SIZE_I32),
-            );
-            if let Some(chunk) = self.chunks.get(&chunk_pos) {
-                if chunk.tiles[local_pos.x as usize][local_pos.y as usize] {
-                    // cannot place, field occupied
-                    return false;
-                } else {
-                    // field is free -> continue checking
-                }
-            } else {
-                // Chunk does not exist -> not loaded yet -> placement fails
-                // TODO: handle error and chunk loading properly
-                return false;
-            }
+    /// Unloads the chunk at `pos` and despawns its [`ChunkEntity`].
+    ///
+    /// The chunk state is kept and restored by the next [`Map::load_chunk`], unless nothing
+    /// occupies the chunk and its terrain equals the one returned by `generate`. Such chunks
+    /// are generated again when they are loaded, so exploring does not keep growing the map.
+    ///
+    /// # Returns
+    /// - `true` if the chunk was loaded before this call.
+    pub fn unload_chunk(
+        &mut self,
+        pos: IVec2,
+        generate: impl FnOnce(IVec2) -> ChunkTerrain,
+        commands: &mut Commands,
+    ) -> bool {
+        let Some(chunk) = self.chunks.remove(&pos) else {
+            return false;
+        };
+        let occupied = chunk.tiles.iter().flatten().any(Option::is_some);
+        if occupied || chunk.terrain != generate(pos) {
+            self.unloaded_chunks.insert(pos, chunk);
+        }
+        self.changed_chunks.remove(&pos);
+        self.streamed_chunks.insert(pos);
+        self.navigation_revision += 1;
+        if let Some(entity) = self.chunk_entities.remove(&pos) {
+            commands.entity(entity).despawn();
+        }
+        true
+    }
+
+    /// Removes all chunks, loaded or not, and everything placed on them.
+    /// Despawns the [`ChunkEntity`] of every loaded chunk, but not the placed entities.
+    pub fn clear(&mut self, commands: &mut Commands) {
+        for entity in self.chunk_entities.values() {
+            commands.entity(*entity).despawn();
+        }
+        *self = Self {
+            navigation_revision: self.navigation_revision + 1,
+            ..Self::default()
+        };
+    }
+
+    /// Stores the terrain of a chunk that is loaded on demand later, replacing any state
+    /// the chunk had. Used to restore saved chunks.
+    pub fn restore_chunk(&mut self, pos: IVec2, terrain: ChunkTerrain) {
+        if let Some(chunk) = self.chunks.get_mut(&pos) {
+            *chunk = ChunkData::new(terrain);
+            self.changed_chunks.insert(pos);
+            self.streamed_chunks.insert(pos);
+            self.navigation_revision += 1;
+        } else {
+            self.unloaded_chunks.insert(pos, ChunkData::new(terrain));
+        }
+    }
+
+    /// Terrain of every chunk the map knows, loaded or not.
+    pub fn all_chunk_terrain(&self) -> impl Iterator<Item = (IVec2, &ChunkTerrain)> {
+        self.chunks
+            .iter()
+            .chain(self.unloaded_chunks.iter())
+            .map(|(pos, chunk)| (*pos, &chunk.terrain))
+    }
+
+    /// Positions of all loaded chunks.
+    pub fn loaded_chunks(&self) -> impl Iterator<Item = IVec2> + '_ {
+        self.chunks.keys().copied()
+    }
+
+    /// Returns the [`ChunkEntity`] of the chunk at `chunk_pos` if it is loaded.
+    pub fn chunk_entity(&self, chunk_pos: IVec2) -> Option<Entity> {
+        self.chunk_entities.get(&chunk_pos).copied()
+    }
+
+    /// Returns the terrain of every tile of the chunk at `chunk_pos` if it is loaded.
+    pub fn chunk_terrain(&self, chunk_pos: IVec2) -> Option<&ChunkTerrain> {
+        self.chunks.get(&chunk_pos).map(|chunk| &chunk.terrain)
+    }
+
+    /// Returns the loaded chunks whose terrain changed since the last call,
+    /// including chunks that were loaded since then.
+    pub fn take_changed_chunks(&mut self) -> Vec<IVec2> {
+        self.changed_chunks.drain().collect()
+    }
+
+    /// Revision of everything that affects where units can move. It changes whenever tiles
+    /// are occupied or freed, or chunks are loaded or unloaded, so cached navigation data
+    /// built at an older revision is outdated.
+    pub fn navigation_revision(&self) -> u64 {
+        self.navigation_revision
+    }
+
+    /// Returns the global tile positions that were occupied or freed since the last call.
+    pub fn take_changed_tiles(&mut self) -> Vec<IVec2> {
+        self.changed_tiles.drain().collect()
+    }
+
+    /// Returns the chunks that were loaded, unloaded or restored while loaded
+    /// since the last call.
+    pub fn take_streamed_chunks(&mut self) -> Vec<IVec2> {
+        self.streamed_chunks.drain().collect()
+    }
+
+    /// Returns `true` if the global position lies within the world bounds.
+    #[inline]
+    pub fn in_bounds(global_pos: IVec2) -> bool {
+        let (chunk_pos, _) = Self::global_to_chunk(global_pos);
+        chunk_pos
+            .cmpge(IVec2::splat(-WORLD_HALF_EXTENT_CHUNKS))
+            .all()
+            && chunk_pos
+                .cmplt(IVec2::splat(WORLD_HALF_EXTENT_CHUNKS))
+                .all()
+    }
+
+    /// Checks whether the tiles of `occlusion_map` relative to `pos` could be occupied,
+    /// without changing the map.
+    ///
+    /// # Returns
+    /// - `Ok(())` if placement is possible.
+    /// - `Err(errors)` with one [`PlacementError`] for every blocking tile, in the order
+    ///   of `occlusion_map`.
+    pub fn can_place(
+        &self,
+        pos: IVec2,
+        occlusion_map: &[IVec2],
+        terrain: &TerrainRegistry,
+    ) -> Result<(), Vec<PlacementError>> {
+        let errors: Vec<PlacementError> = occlusion_map
+            .iter()
+            .filter_map(|offset| self.check_tile(pos + offset, terrain).err())
+            .collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn check_tile(
+        &self,
+        global_pos: IVec2,
+        terrain: &TerrainRegistry,
+    ) -> Result<(), PlacementError> {
+        if !Self::in_bounds(global_pos) {
+            return Err(PlacementError::OutOfBounds {
+                position: global_pos,
+            });
+        }
+        let (chunk_pos, local_pos) = Self::global_to_chunk(global_pos);
+        let Some(chunk) = self.chunks.get(&chunk_pos) else {
+            // Chunk does not exist -> not loaded yet -> placement fails
+            return Err(PlacementError::ChunkNotLoaded {
+                position: global_pos,
+                chunk: chunk_pos,
+            });
+        };
+        if let Some(occupant) = chunk.get(local_pos) {
+            return Err(PlacementError::Occupied {
+                position: global_pos,
+                occupant,
+            });
+        }
+        if !terrain.get(chunk.terrain(local_pos)).buildable {
+            return Err(PlacementError::TerrainDisallowed {
+                position: global_pos,
+            });
+        }
+        Ok(())
+    }
+
+    /// Tries to occupy the tiles of `occlusion_map` relative to `pos` on behalf of `owner`.
+    ///
+    /// Nothing is changed if placement fails; the error describes the first blocking tile.
+    /// Use [`Map::can_place`] to get every blocking tile.
+    pub fn try_place(
+        &mut self,
+        pos: IVec2,
+        occlusion_map: &[IVec2],
+        owner: Entity,
+        terrain: &TerrainRegistry,
+    ) -> Result<(), PlacementError> {
+        if let Err(errors) = self.can_place(pos, occlusion_map, terrain) {
+            return Err(errors
+                .into_iter()
+                .next()
+                .expect("can_place reports at least one error"));
         }
 
         // placement possible
+        let mut footprint = Vec::with_capacity(occlusion_map.len());
         for offset in occlusion_map {
             let place_pos = pos + offset;
-            let chunk_pos = IVec2::new(
-                place_pos.x.div_euclid(CHUNK_SIZE_I32),
-                place_pos.y.div_euclid(CHUNK_SIZE_I32),
-            );
-            let local_pos = IVec2::new(
-                place_pos.x.rem_euclid(CHUNK_SIZE_I32),
-                place_pos.y.rem_euclid(CHUNK_SIZE_I32),
-            );
+            let (chunk_pos, local_pos) = Self::global_to_chunk(place_pos);
             let chunk = self
                 .chunks
                 .get_mut(&chunk_pos)
                 .expect("Chunk must exist here; we checked before");
-            assert!(!chunk.tiles[local_pos.x as usize][local_pos.y as usize]);
-            chunk.tiles[local_pos.x as usize][local_pos.y as usize] = true;
+            assert!(chunk.get(local_pos).is_none());
+            chunk.set(local_pos, Some(owner));
+            footprint.push(place_pos);
         }
+        self.changed_tiles.extend(&footprint);
+        self.navigation_revision += 1;
+        self.footprints.insert(owner, footprint);
         // placement successful
-        true
+        Ok(())
+    }
+
+    /// Releases all tiles occupied by `owner`.
+    ///
+    /// Returns the freed global tile positions, or `None` if `owner` has nothing placed.
+    pub fn remove(&mut self, owner: Entity) -> Option<Vec<IVec2>> {
+        let footprint = self.footprints.remove(&owner)?;
+        for &global_pos in &footprint {
+            let (chunk_pos, local_pos) = Self::global_to_chunk(global_pos);
+            if let Some(chunk) = self
+                .chunks
+                .get_mut(&chunk_pos)
+                .or_else(|| self.unloaded_chunks.get_mut(&chunk_pos))
+            {
+                chunk.set(local_pos, None);
+            }
+        }
+        self.changed_tiles.extend(&footprint);
+        self.navigation_revision += 1;
+        Some(footprint)
+    }
+
+    /// Moves the footprint of `owner` to the tiles of `occlusion_map` relative to `pos`.
+    ///
+    /// Tiles occupied by `owner` itself do not block. Nothing is changed if placement
+    /// fails; the error describes the first blocking tile.
+    pub fn replace_footprint(
+        &mut self,
+        owner: Entity,
+        pos: IVec2,
+        occlusion_map: &[IVec2],
+        terrain: &TerrainRegistry,
+    ) -> Result<(), PlacementError> {
+        for offset in occlusion_map {
+            match self.check_tile(pos + offset, terrain) {
+                Err(PlacementError::Occupied { occupant, .. }) if occupant == owner => {}
+                result => result?,
+            }
+        }
+        self.remove(owner);
+        self.try_place(pos, occlusion_map, owner, terrain)
+    }
+
+    /// Global tile positions occupied by `owner`, or `None` if it has nothing placed.
+    pub fn footprint(&self, owner: Entity) -> Option<&[IVec2]> {
+        self.footprints.get(&owner).map(Vec::as_slice)
+    }
+
+    /// Occupies the given global tiles on behalf of `owner`, in loaded and unloaded chunks
+    /// alike, without checking the terrain. Used to restore saved footprints.
+    ///
+    /// Nothing is changed if a tile is occupied or belongs to a chunk the map does not know.
+    pub fn restore_footprint(
+        &mut self,
+        owner: Entity,
+        footprint: &[IVec2],
+    ) -> Result<(), PlacementError> {
+        for &global_pos in footprint {
+            let (chunk_pos, local_pos) = Self::global_to_chunk(global_pos);
+            let chunk = self
+                .chunks
+                .get(&chunk_pos)
+                .or_else(|| self.unloaded_chunks.get(&chunk_pos))
+                .ok_or(PlacementError::ChunkNotLoaded {
+                    position: global_pos,
+                    chunk: chunk_pos,
+                })?;
+            if let Some(occupant) = chunk.get(local_pos) {
+                return Err(PlacementError::Occupied {
+                    position: global_pos,
+                    occupant,
+                });
+            }
+        }
+        for &global_pos in footprint {
+            let (chunk_pos, local_pos) = Self::global_to_chunk(global_pos);
+            let chunk = match self.chunks.get_mut(&chunk_pos) {
+                Some(chunk) => chunk,
+                None => self
+                    .unloaded_chunks
+                    .get_mut(&chunk_pos)
+                    .expect("Chunk must exist here; we checked before"),
+            };
+            chunk.set(local_pos, Some(owner));
+        }
+        self.changed_tiles.extend(footprint);
+        self.navigation_revision += 1;
+        self.footprints.insert(owner, footprint.to_vec());
+        Ok(())
+    }
+
+    /// Returns the entity occupying the given global position, if any.
+    /// This returns `None` for free tiles and for tiles in chunks that are not loaded.
+    pub fn occupant_at(&self, global_pos: IVec2) -> Option<Entity> {
+        let (chunk_pos, local_pos) = Self::global_to_chunk(global_pos);
+        self.chunks
+            .get(&chunk_pos)
+            .and_then(|chunk| chunk.get(local_pos))
+    }
+
+    /// Returns the terrain at the given global position.
+    /// This returns `None` for tiles in chunks that are not loaded.
+    pub fn terrain_at(&self, global_pos: IVec2) -> Option<TerrainId> {
+        let (chunk_pos, local_pos) = Self::global_to_chunk(global_pos);
+        self.chunks
+            .get(&chunk_pos)
+            .map(|chunk| chunk.terrain(local_pos))
     }
 
     /// Checks if a global position is occupied.
     /// This returns true if the position is occupied or if the chunk is not loaded.
     pub fn is_occupied(&self, chunk_pos: IVec2, local_pos: IVec2) -> bool {
         if let Some(chunk) = self.chunks.get(&chunk_pos) {
-            chunk.tiles[local_pos.x as usize][local_pos.y as usize]
+            chunk.get(local_pos).is_some()
         } else {
             // Chunk does not exist -> not loaded yet -> consider occupied
             true
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::world::CommandQueue;
+
+    use super::*;
+    use crate::pathfinding::tests::{grass, test_map, test_terrain};
+
+    #[test]
+    fn unloading_drops_chunks_that_can_be_generated_again() {
+        let terrain = test_terrain(1.0);
+        let chunks = [IVec2::ZERO, IVec2::new(1, 0), IVec2::new(2, 0)];
+        let occupied = IVec2::new(CHUNK_SIZE_I32 + 1, 1);
+        let mut map = test_map(&terrain, &chunks, grass, &[occupied]);
+        let generated = [[terrain.id("test:grass").unwrap(); CHUNK_SIZE]; CHUNK_SIZE];
+        let mud = [[terrain.id("test:mud").unwrap(); CHUNK_SIZE]; CHUNK_SIZE];
+        map.restore_chunk(IVec2::new(2, 0), mud);
+
+        let world = World::new();
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &world);
+        for chunk_pos in chunks {
+            assert!(map.unload_chunk(chunk_pos, |_| generated, &mut commands));
+        }
+
+        // the occupied chunk and the one with changed terrain are kept
+        let mut kept: Vec<IVec2> = map.all_chunk_terrain().map(|(pos, _)| pos).collect();
+        kept.sort_by_key(|pos| pos.x);
+        assert_eq!(kept, [IVec2::new(1, 0), IVec2::new(2, 0)]);
+        map.load_chunk(IVec2::new(1, 0), |_| unreachable!(), &mut commands);
+        assert!(map.occupant_at(occupied).is_some());
+    }
+}
@@ -0,0 +1,1204 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, hash_map::Entry},
+    marker::PhantomData,
+    path::PathBuf,
+};
+
+use bevy::{
+    asset::{
+        AssetLoadError, AssetLoader, AssetPath, LoadContext, UntypedAssetId,
+        UntypedAssetLoadFailedEvent,
+        io::{AssetSourceId, Reader},
+    },
+    ecs::system::SystemParam,
+    prelude::*,
+    tasks::{IoTaskPool, Task, futures::check_ready, futures_lite::StreamExt},
+};
+use semver::{Version, VersionReq};
+use serde::{Deserialize, de::DeserializeOwned};
+
+use crate::{
+    BuildingEntryReplaced, BuildingRegistry,
+    building_definitions::{BuildingDefinition, BuildingEntryFactory},
+    terrain::{TerrainDefinitions, TerrainKind, TerrainRegistry},
+    toasts::ToastMessage,
+    unit_definitions::{UnitDefinition, UnitEntryFactory},
+    units::{UnitEntryReplaced, UnitRegistry},
+    user_controls::{
+        CommandDefinitions, CommandRegistry, ControlPanelDefinitions, ControlPanelRegistry,
+        parse_key_code,
+    },
+};
+
+/// Folder, relative to the asset folder, containing one directory per content module.
+const MODULES_FOLDER: &str = "modules";
+/// Name of the manifest file every content module directory must contain.
+const MANIFEST_FILE_NAME: &str = "module.ron";
+
+/// Asset loader for any asset that is deserialized from a single RON file.
+pub struct RonAssetLoader<A> {
+    extensions: &'static [&'static str],
+    _asset: PhantomData<fn() -> A>,
+}
+
+impl<A> RonAssetLoader<A> {
+    pub const fn new(extensions: &'static [&'static str]) -> Self {
+        Self {
+            extensions,
+            _asset: PhantomData,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RonAssetLoaderError {
+    Io(std::io::Error),
+    Ron(ron::error::SpannedError),
+}
+
+impl std::fmt::Display for RonAssetLoaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "could not read file: {}", error),
+            Self::Ron(error) => write!(f, "could not parse file: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for RonAssetLoaderError {}
+
+impl From<std::io::Error> for RonAssetLoaderError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<ron::error::SpannedError> for RonAssetLoaderError {
+    fn from(error: ron::error::SpannedError) -> Self {
+        Self::Ron(error)
+    }
+}
+
+impl<A> AssetLoader for RonAssetLoader<A>
+where
+    A: Asset + DeserializeOwned,
+{
+    type Asset = A;
+    type Settings = ();
+    type Error = RonAssetLoaderError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &Self::Settings,
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        Ok(ron::de::from_bytes(&bytes)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        self.extensions
+    }
+}
+
+/// Dependency of a content module on another module.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ModuleDependency {
+    pub namespace: String,
+    /// Semantic version requirement the dependency has to satisfy, e.g. `"^0.1"`.
+    pub version: VersionReq,
+}
+
+/// Manifest of a content module, read from its `module.ron`.
+#[derive(Asset, TypePath, Debug, Clone, Deserialize)]
+pub struct ModuleManifest {
+    /// Namespace of all ids defined by the module, e.g. `core` for `core:barracks`.
+    pub namespace: String,
+    pub version: Version,
+    /// Modules that have to be applied before this one.
+    #[serde(default)]
+    pub dependencies: Vec<ModuleDependency>,
+    /// Ids defined by dependencies that this module replaces, e.g. `core:barracks`.
+    /// Redefining an id that is not listed here is an error.
+    #[serde(default)]
+    pub overrides: Vec<String>,
+}
+
+impl ModuleManifest {
+    fn depends_on(&self, namespace: &str) -> bool {
+        self.dependencies
+            .iter()
+            .any(|dependency| dependency.namespace == namespace)
+    }
+}
+
+/// A discovered content module and the loading state of its files.
+struct ContentModule {
+    manifest: ModuleManifest,
+    /// Asset path of the module directory.
+    path: String,
+    applied: bool,
+}
+
+impl ContentModule {
+    /// Returns `true` if the asset at `path` lies inside the module directory.
+    fn contains(&self, path: &str) -> bool {
+        path.strip_prefix(self.path.as_str())
+            .is_some_and(|rest| rest.starts_with('/'))
+    }
+}
+
+/// Kinds of registry entries a content module can define.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum ContentKind {
+    Building,
+    Command,
+    ControlPanel,
+    Terrain,
+    Unit,
+}
+
+impl std::fmt::Display for ContentKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Building => write!(f, "building"),
+            Self::Command => write!(f, "command"),
+            Self::ControlPanel => write!(f, "control panel"),
+            Self::Terrain => write!(f, "terrain"),
+            Self::Unit => write!(f, "unit"),
+        }
+    }
+}
+
+/// Content modules discovered in the `modules` asset folder.
+///
+/// Each module lives in its own directory containing a `module.ron` manifest and any number of
+/// `*.building.ron`, `*.commands.ron`, `*.panels.ron`, `*.terrain.ron` and `*.unit.ron` files,
+/// which may be nested in sub directories. Every id a module defines must be prefixed with its namespace, unless
+/// the module explicitly overrides an id of one of its dependencies.
+///
+/// All files in the folder are loaded through the [`AssetServer`]. Once they finished loading,
+/// the manifests are read and modules are applied in dependency order; a module is only applied
+/// after all of its dependencies were applied.
+#[derive(Resource, Default)]
+pub struct ModuleLoader {
+    /// Task loading the files of the modules folder, until it finished.
+    files_task: Option<Task<Result<ModuleFiles, String>>>,
+    /// Handles of all loaded module files, which keep the definitions loaded.
+    files: Vec<UntypedHandle>,
+    /// Whether the manifests in the modules folder were read.
+    discovered: bool,
+    modules: Vec<ContentModule>,
+    /// Namespace of the module that defined each id, per kind of content.
+    owners: HashMap<(ContentKind, String), String>,
+}
+
+/// How a module came to own an id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Claim {
+    /// The id was not defined before.
+    New,
+    /// The id replaces a definition of the given dependency.
+    Override { previous_owner: String },
+    /// The module already owns the id and redefines it from a reloaded file.
+    Reload,
+}
+
+impl ModuleLoader {
+    /// Manifests of all modules whose content was applied, in load order.
+    pub fn applied_modules(&self) -> impl Iterator<Item = &ModuleManifest> {
+        self.modules
+            .iter()
+            .filter(|module| module.applied)
+            .map(|module| &module.manifest)
+    }
+
+    /// Records the module of `manifest` as the owner of `id`.
+    ///
+    /// Ids listed in the manifest's overrides must already be defined by one of its
+    /// dependencies. All other ids must lie inside the module's namespace and must not be
+    /// defined by any module yet. When `reload` is set, the module may redefine ids it
+    /// already owns.
+    fn claim(
+        &mut self,
+        kind: ContentKind,
+        id: &str,
+        manifest: &ModuleManifest,
+        reload: bool,
+    ) -> Result<Claim, String> {
+        let namespace = &manifest.namespace;
+        if reload && self.owners.get(&(kind, id.to_string())) == Some(namespace) {
+            return Ok(Claim::Reload);
+        }
+        if manifest.overrides.iter().any(|overridden| overridden == id) {
+            let Some(owner) = self.owners.get_mut(&(kind, id.to_string())) else {
+                return Err(format!(
+                    "cannot override {} '{}': it is not defined by any module",
+                    kind, id
+                ));
+            };
+            if !manifest.depends_on(owner) {
+                return Err(format!(
+                    "cannot override {} '{}': it is defined by module '{}', which is not a dependency",
+                    kind, id, owner
+                ));
+            }
+            let previous_owner = std::mem::replace(owner, namespace.clone());
+            return Ok(Claim::Override { previous_owner });
+        }
+        match id.split_once(':') {
+            Some((id_namespace, name)) if id_namespace == namespace && !name.is_empty() => {}
+            _ => {
+                return Err(format!(
+                    "{} id '{}' must have the form '{}:name'",
+                    kind, id, namespace
+                ));
+            }
+        }
+        match self.owners.entry((kind, id.to_string())) {
+            Entry::Vacant(e) => {
+                e.insert(namespace.clone());
+                Ok(Claim::New)
+            }
+            Entry::Occupied(e) => Err(format!(
+                "{} '{}' is already defined by module '{}'; list it in 'overrides' to replace it",
+                kind,
+                id,
+                e.get()
+            )),
+        }
+    }
+
+    /// Returns the module whose directory contains the asset at `path`.
+    fn module_for_path(&self, path: &str) -> Option<&ContentModule> {
+        self.modules.iter().find(|module| module.contains(path))
+    }
+}
+
+/// Run condition that is `true` once the modules were discovered and every one of them was
+/// applied.
+pub fn modules_applied(loader: Res<ModuleLoader>) -> bool {
+    loader.discovered && loader.modules.iter().all(|module| module.applied)
+}
+
+/// Directory name of a module inside the modules folder and its manifest.
+type DiscoveredModule = (String, ModuleManifest);
+
+/// Orders manifests so that every module comes after its dependencies.
+///
+/// Modules with missing or incompatible dependencies, modules in dependency cycles and
+/// modules depending on any of those are left out. Each of them is returned together
+/// with the reason. Independent modules keep the order of their namespaces.
+fn resolve_load_order(
+    manifests: Vec<DiscoveredModule>,
+) -> (Vec<DiscoveredModule>, Vec<(String, String)>) {
+    let versions: HashMap<&str, &Version> = manifests
+        .iter()
+        .map(|(_, manifest)| (manifest.namespace.as_str(), &manifest.version))
+        .collect();
+    let mut rejected: Vec<(String, String)> = Vec::new();
+    for (_, manifest) in &manifests {
+        for dependency in &manifest.dependencies {
+            let problem = match versions.get(dependency.namespace.as_str()) {
+                None => format!("missing dependency '{}'", dependency.namespace),
+                Some(version) if !dependency.version.matches(version) => format!(
+                    "requires '{}' {}, but {} is installed",
+                    dependency.namespace, dependency.version, version
+                ),
+                Some(_) => continue,
+            };
+            rejected.push((manifest.namespace.clone(), problem));
+            break;
+        }
+    }
+
+    // modules depending on rejected modules cannot be loaded either
+    let mut rejected_namespaces: HashSet<String> = rejected
+        .iter()
+        .map(|(namespace, _)| namespace.clone())
+        .collect();
+    loop {
+        let newly_rejected: Vec<(String, String)> = manifests
+            .iter()
+            .filter(|(_, manifest)| !rejected_namespaces.contains(&manifest.namespace))
+            .filter_map(|(_, manifest)| {
+                manifest
+                    .dependencies
+                    .iter()
+                    .find(|dependency| rejected_namespaces.contains(&dependency.namespace))
+                    .map(|dependency| {
+                        (
+                            manifest.namespace.clone(),
+                            format!("dependency '{}' cannot be loaded", dependency.namespace),
+                        )
+                    })
+            })
+            .collect();
+        if newly_rejected.is_empty() {
+            break;
+        }
+        for (namespace, problem) in newly_rejected {
+            rejected_namespaces.insert(namespace.clone());
+            rejected.push((namespace, problem));
+        }
+    }
+
+    // Kahn's algorithm, always picking the alphabetically first module that is ready
+    let mut pending: HashMap<String, DiscoveredModule> = manifests
+        .into_iter()
+        .filter(|(_, manifest)| !rejected_namespaces.contains(&manifest.namespace))
+        .map(|(directory, manifest)| (manifest.namespace.clone(), (directory, manifest)))
+        .collect();
+    let mut order = Vec::with_capacity(pending.len());
+    let mut ordered: HashSet<String> = HashSet::new();
+    loop {
+        let ready: BTreeSet<&String> = pending
+            .iter()
+            .filter(|(_, (_, manifest))| {
+                manifest
+                    .dependencies
+                    .iter()
+                    .all(|dependency| ordered.contains(&dependency.namespace))
+            })
+            .map(|(namespace, _)| namespace)
+            .collect();
+        let Some(next) = ready.first().map(|namespace| (*namespace).clone()) else {
+            break;
+        };
+        let module = pending.remove(&next).expect("ready modules are pending");
+        ordered.insert(next);
+        order.push(module);
+    }
+
+    // whatever is left is part of, or depends on, a dependency cycle
+    let mut cyclic: Vec<&String> = pending.keys().collect();
+    cyclic.sort();
+    for namespace in &cyclic {
+        rejected.push((
+            (*namespace).clone(),
+            format!(
+                "dependency cycle between modules {}",
+                cyclic
+                    .iter()
+                    .map(|namespace| format!("'{}'", namespace))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        ));
+    }
+    (order, rejected)
+}
+
+/// Files in the modules folder.
+#[derive(Default)]
+struct ModuleFiles {
+    loaded: Vec<UntypedHandle>,
+    /// Asset path and error of every file that failed to load.
+    failed: Vec<(String, String)>,
+}
+
+/// Loads every file in the modules folder and its sub directories that has an asset loader.
+///
+/// Unlike [`AssetServer::load_folder`], a file that fails to load does not fail the others;
+/// it is returned in [`ModuleFiles::failed`] instead.
+async fn load_module_files(asset_server: AssetServer) -> Result<ModuleFiles, String> {
+    let source = asset_server
+        .get_source(AssetSourceId::Default)
+        .map_err(|error| error.to_string())?;
+    let reader = source.reader();
+    let mut files = ModuleFiles::default();
+    let mut directories = vec![PathBuf::from(MODULES_FOLDER)];
+    while let Some(directory) = directories.pop() {
+        let mut entries = reader
+            .read_directory(&directory)
+            .await
+            .map_err(|error| error.to_string())?;
+        while let Some(path) = entries.next().await {
+            if reader
+                .is_directory(&path)
+                .await
+                .map_err(|error| error.to_string())?
+            {
+                directories.push(path);
+                continue;
+            }
+            match asset_server
+                .load_untyped_async(AssetPath::from_path(&path))
+                .await
+            {
+                Ok(handle) => files.loaded.push(handle),
+                // not a definition file
+                Err(
+                    AssetLoadError::MissingAssetLoaderForExtension(_)
+                    | AssetLoadError::MissingAssetLoaderForTypeName(_),
+                ) => {}
+                Err(error) => files
+                    .failed
+                    .push((path.to_string_lossy().into_owned(), error.to_string())),
+            }
+        }
+    }
+    Ok(files)
+}
+
+fn load_modules_folder(mut loader: ResMut<ModuleLoader>, asset_server: Res<AssetServer>) {
+    let asset_server = asset_server.clone();
+    loader.files_task = Some(IoTaskPool::get().spawn(load_module_files(asset_server)));
+}
+
+/// Splits the asset path of a file inside a module into the name of the module directory and
+/// the path of the file inside it, e.g. `core` and `module.ron` for `modules/core/module.ron`.
+fn split_module_path(path: &str) -> Option<(&str, &str)> {
+    path.strip_prefix(MODULES_FOLDER)?
+        .strip_prefix('/')?
+        .split_once('/')
+}
+
+/// Reads the manifests of all modules once the files of the modules folder finished loading
+/// and determines the order the modules are applied in.
+fn discover_modules(
+    mut loader: ResMut<ModuleLoader>,
+    manifest_assets: Res<Assets<ModuleManifest>>,
+    mut toasts: MessageWriter<ToastMessage>,
+) {
+    let Some(task) = &mut loader.files_task else {
+        return;
+    };
+    let Some(result) = check_ready(task) else {
+        return;
+    };
+    loader.files_task = None;
+    loader.discovered = true;
+    let files = match result {
+        Ok(files) => files,
+        Err(error) => {
+            error!("Cannot read modules folder '{}': {}", MODULES_FOLDER, error);
+            return;
+        }
+    };
+
+    // manifest of every module directory, sorted to load in a stable order regardless of
+    // the asset source
+    let mut directories: BTreeMap<String, Option<Result<UntypedAssetId, String>>> = BTreeMap::new();
+    let loaded = files.loaded.iter().filter_map(|handle| {
+        let path = handle.path()?.to_string();
+        Some((path, Ok(handle.id())))
+    });
+    let failed = files
+        .failed
+        .iter()
+        .map(|(path, error)| (path.clone(), Err(error.clone())));
+    for (path, file) in loaded.chain(failed) {
+        let Some((directory_name, file_path)) = split_module_path(&path) else {
+            continue;
+        };
+        let manifest = directories.entry(directory_name.to_string()).or_default();
+        if file_path == MANIFEST_FILE_NAME {
+            *manifest = Some(file);
+        }
+    }
+    // other files that failed to load are reported once their module is known
+    let failed_files: Vec<(String, String)> = files
+        .failed
+        .into_iter()
+        .filter(|(path, _)| {
+            split_module_path(path).is_some_and(|(_, file_path)| file_path != MANIFEST_FILE_NAME)
+        })
+        .collect();
+    loader.files = files.loaded;
+
+    let mut manifests: Vec<DiscoveredModule> = Vec::new();
+    for (directory_name, manifest_file) in directories {
+        let manifest_path = format!(
+            "{}/{}/{}",
+            MODULES_FOLDER, directory_name, MANIFEST_FILE_NAME
+        );
+        let manifest_id = match manifest_file {
+            Some(Ok(manifest_id)) => manifest_id,
+            Some(Err(error)) => {
+                report_module_error(
+                    &mut toasts,
+                    &directory_name,
+                    &format!("invalid manifest '{}': {}", manifest_path, error),
+                );
+                continue;
+            }
+            None => {
+                report_module_error(
+                    &mut toasts,
+                    &directory_name,
+                    &format!("missing manifest '{}'", manifest_path),
+                );
+                continue;
+            }
+        };
+        let Some(manifest) = manifest_id
+            .try_typed::<ModuleManifest>()
+            .ok()
+            .and_then(|id| manifest_assets.get(id))
+        else {
+            report_module_error(
+                &mut toasts,
+                &directory_name,
+                &format!(
+                    "invalid manifest '{}': not a module manifest",
+                    manifest_path
+                ),
+            );
+            continue;
+        };
+        if let Some((existing, _)) = manifests
+            .iter()
+            .find(|(_, existing)| existing.namespace == manifest.namespace)
+        {
+            report_module_error(
+                &mut toasts,
+                &directory_name,
+                &format!(
+                    "namespace '{}' is already used by module in '{}/{}'",
+                    manifest.namespace, MODULES_FOLDER, existing
+                ),
+            );
+            continue;
+        }
+        manifests.push((directory_name, manifest.clone()));
+    }
+
+    let (order, rejected) = resolve_load_order(manifests);
+    for (namespace, problem) in rejected {
+        report_module_error(&mut toasts, &namespace, &format!("not loaded: {}", problem));
+    }
+    for (directory_name, manifest) in order {
+        let path = format!("{}/{}", MODULES_FOLDER, directory_name);
+        info!(
+            "Discovered module '{}' {} in '{}'",
+            manifest.namespace, manifest.version, path
+        );
+        loader.modules.push(ContentModule {
+            manifest,
+            path,
+            applied: false,
+        });
+    }
+    for (path, error) in failed_files {
+        if let Some(module) = loader.module_for_path(&path) {
+            report_module_error(
+                &mut toasts,
+                &module.manifest.namespace,
+                &format!("failed to load '{}': {}", path, error),
+            );
+        }
+    }
+}
+
+fn report_module_error(toasts: &mut MessageWriter<ToastMessage>, module: &str, message: &str) {
+    error!("Module '{}': {}", module, message);
+    toasts.write(ToastMessage {
+        content: format!("Module '{}': {}", module, message),
+    });
+}
+
+/// Loaded definition files of all content modules.
+#[derive(SystemParam)]
+struct ContentAssets<'w> {
+    buildings: Res<'w, Assets<BuildingDefinition>>,
+    commands: Res<'w, Assets<CommandDefinitions>>,
+    control_panels: Res<'w, Assets<ControlPanelDefinitions>>,
+    terrain: Res<'w, Assets<TerrainDefinitions>>,
+    units: Res<'w, Assets<UnitDefinition>>,
+}
+
+/// Registries content modules are loaded into.
+#[derive(SystemParam)]
+struct ContentRegistries<'w, 's> {
+    buildings: ResMut<'w, BuildingRegistry>,
+    /// Both factories create meshes and materials, so only one can be used at a time.
+    entry_factories: ParamSet<'w, 's, (BuildingEntryFactory<'w>, UnitEntryFactory<'w>)>,
+    commands: ResMut<'w, CommandRegistry>,
+    control_panels: ResMut<'w, ControlPanelRegistry>,
+    terrain: ResMut<'w, TerrainRegistry>,
+    units: ResMut<'w, UnitRegistry>,
+    replaced_buildings: MessageWriter<'w, BuildingEntryReplaced>,
+    replaced_units: MessageWriter<'w, UnitEntryReplaced>,
+}
+
+fn report_failed_files(
+    mut failed_events: MessageReader<UntypedAssetLoadFailedEvent>,
+    loader: Res<ModuleLoader>,
+    mut toasts: MessageWriter<ToastMessage>,
+) {
+    for event in failed_events.read() {
+        let path = event.path.to_string();
+        // files that fail on the initial load are reported by `discover_modules`
+        if let Some(module) = loader
+            .module_for_path(&path)
+            .filter(|module| module.applied)
+        {
+            report_module_error(
+                &mut toasts,
+                &module.manifest.namespace,
+                &format!("failed to load '{}': {}", path, event.error),
+            );
+        }
+    }
+}
+
+/// Outcome of applying a single definition file.
+struct AppliedFile {
+    kind: ContentKind,
+    /// Number of registry entries defined by the file that were registered.
+    applied: usize,
+    errors: Vec<String>,
+}
+
+/// Registers the definitions of one loaded file of the module of `manifest`.
+///
+/// Returns `None` if the asset is not a loaded definition file. When `reload` is set,
+/// entries the module already defined are replaced instead of rejected as duplicates.
+fn apply_definition_file(
+    loader: &mut ModuleLoader,
+    assets: &ContentAssets,
+    registries: &mut ContentRegistries,
+    manifest: &ModuleManifest,
+    id: UntypedAssetId,
+    reload: bool,
+) -> Option<AppliedFile> {
+    let namespace = &manifest.namespace;
+    let file = if let Ok(id) = id.try_typed::<BuildingDefinition>() {
+        let definition = assets.buildings.get(id)?;
+        let mut file = AppliedFile {
+            kind: ContentKind::Building,
+            applied: 0,
+            errors: Vec::new(),
+        };
+        match apply_building(loader, registries, manifest, definition, reload) {
+            Ok(()) => file.applied += 1,
+            Err(error) => file.errors.push(error),
+        }
+        file
+    } else if let Ok(id) = id.try_typed::<CommandDefinitions>() {
+        let definitions = assets.commands.get(id)?;
+        let mut file = AppliedFile {
+            kind: ContentKind::Command,
+            applied: 0,
+            errors: Vec::new(),
+        };
+        for entry in &definitions.0 {
+            match loader.claim(ContentKind::Command, &entry.command_type, manifest, reload) {
+                Ok(Claim::New | Claim::Reload) => {}
+                Ok(Claim::Override { previous_owner }) => info!(
+                    "Module '{}' overrides command '{}' of module '{}'",
+                    namespace, entry.command_type, previous_owner
+                ),
+                Err(error) => {
+                    file.errors.push(error);
+                    continue;
+                }
+            }
+            registries.commands.register(entry.clone());
+            file.applied += 1;
+        }
+        file
+    } else if let Ok(id) = id.try_typed::<ControlPanelDefinitions>() {
+        let definitions = assets.control_panels.get(id)?;
+        let mut file = AppliedFile {
+            kind: ContentKind::ControlPanel,
+            applied: 0,
+            errors: Vec::new(),
+        };
+        for (entity_type, tree) in &definitions.0 {
+            match loader.claim(ContentKind::ControlPanel, entity_type, manifest, reload) {
+                Ok(Claim::New | Claim::Reload) => {}
+                Ok(Claim::Override { previous_owner }) => info!(
+                    "Module '{}' overrides control panel '{}' of module '{}'",
+                    namespace, entity_type, previous_owner
+                ),
+                Err(error) => {
+                    file.errors.push(error);
+                    continue;
+                }
+            }
+            registries
+                .control_panels
+                .register(entity_type.clone(), tree.clone());
+            file.applied += 1;
+        }
+        file
+    } else if let Ok(id) = id.try_typed::<TerrainDefinitions>() {
+        let definitions = assets.terrain.get(id)?;
+        let mut file = AppliedFile {
+            kind: ContentKind::Terrain,
+            applied: 0,
+            errors: Vec::new(),
+        };
+        for kind in &definitions.0 {
+            match apply_terrain(loader, registries, manifest, kind, reload) {
+                Ok(()) => file.applied += 1,
+                Err(error) => file.errors.push(error),
+            }
+        }
+        file
+    } else if let Ok(id) = id.try_typed::<UnitDefinition>() {
+        let definition = assets.units.get(id)?;
+        let mut file = AppliedFile {
+            kind: ContentKind::Unit,
+            applied: 0,
+            errors: Vec::new(),
+        };
+        match apply_unit(loader, registries, manifest, definition, reload) {
+            Ok(()) => file.applied += 1,
+            Err(error) => file.errors.push(error),
+        }
+        file
+    } else {
+        return None;
+    };
+    Some(file)
+}
+
+/// Registers a building definition of the module of `manifest`.
+/// Buildings that replace an existing entry are announced with a [`BuildingEntryReplaced`].
+fn apply_building(
+    loader: &mut ModuleLoader,
+    registries: &mut ContentRegistries,
+    manifest: &ModuleManifest,
+    definition: &BuildingDefinition,
+    reload: bool,
+) -> Result<(), String> {
+    registries
+        .entry_factories
+        .p0()
+        .validate(definition)
+        .map_err(|problems| problems.join("; "))?;
+    let id = &definition.id;
+    if let Some(hotkey) = &definition.hotkey
+        && let Some(other) = parse_key_code(hotkey)
+            .and_then(|key| registries.buildings.building_with_hotkey(key, id))
+    {
+        return Err(format!(
+            "hotkey '{}' is already used by building '{}'",
+            hotkey, other
+        ));
+    }
+    let claim = loader.claim(ContentKind::Building, id, manifest, reload)?;
+    let entry = registries.entry_factories.p0().create(definition);
+    match claim {
+        Claim::New => return registries.buildings.register(id.clone(), entry),
+        Claim::Override { previous_owner } => info!(
+            "Module '{}' overrides building '{}' of module '{}'",
+            manifest.namespace, id, previous_owner
+        ),
+        Claim::Reload => {}
+    }
+    registries
+        .buildings
+        .replace(id, entry)
+        .ok_or_else(|| format!("building '{}' is not registered", id))?;
+    registries
+        .replaced_buildings
+        .write(BuildingEntryReplaced { id: id.clone() });
+    Ok(())
+}
+
+/// Registers a terrain kind of the module of `manifest`.
+fn apply_terrain(
+    loader: &mut ModuleLoader,
+    registries: &mut ContentRegistries,
+    manifest: &ModuleManifest,
+    kind: &TerrainKind,
+    reload: bool,
+) -> Result<(), String> {
+    kind.validate()
+        .map_err(|problems| format!("terrain '{}': {}", kind.id, problems.join("; ")))?;
+    match loader.claim(ContentKind::Terrain, &kind.id, manifest, reload)? {
+        Claim::New => return registries.terrain.register(kind.clone()).map(|_| ()),
+        Claim::Override { previous_owner } => info!(
+            "Module '{}' overrides terrain '{}' of module '{}'",
+            manifest.namespace, kind.id, previous_owner
+        ),
+        Claim::Reload => {}
+    }
+    registries
+        .terrain
+        .replace(kind.clone())
+        .map(|_| ())
+        .ok_or_else(|| format!("terrain '{}' is not registered", kind.id))
+}
+
+/// Registers a unit definition of the module of `manifest`.
+/// Units that replace an existing entry are announced with a [`UnitEntryReplaced`].
+fn apply_unit(
+    loader: &mut ModuleLoader,
+    registries: &mut ContentRegistries,
+    manifest: &ModuleManifest,
+    definition: &UnitDefinition,
+    reload: bool,
+) -> Result<(), String> {
+    definition
+        .validate()
+        .map_err(|problems| problems.join("; "))?;
+    let id = &definition.id;
+    let claim = loader.claim(ContentKind::Unit, id, manifest, reload)?;
+    let entry = registries.entry_factories.p1().create(definition);
+    match claim {
+        Claim::New => return registries.units.register(id.clone(), entry),
+        Claim::Override { previous_owner } => info!(
+            "Module '{}' overrides unit '{}' of module '{}'",
+            manifest.namespace, id, previous_owner
+        ),
+        Claim::Reload => {}
+    }
+    registries
+        .units
+        .replace(id, entry)
+        .ok_or_else(|| format!("unit '{}' is not registered", id))?;
+    registries
+        .replaced_units
+        .write(UnitEntryReplaced { id: id.clone() });
+    Ok(())
+}
+
+/// Registers the content of every module whose files finished loading, once all of its
+/// dependencies were applied.
+/// Files that failed to load are skipped, the rest of the module is still applied.
+fn apply_loaded_modules(
+    mut loader: ResMut<ModuleLoader>,
+    assets: ContentAssets,
+    mut registries: ContentRegistries,
+    mut toasts: MessageWriter<ToastMessage>,
+) {
+    for index in 0..loader.modules.len() {
+        let module = &loader.modules[index];
+        if module.applied {
+            continue;
+        }
+        let dependencies_applied = module.manifest.dependencies.iter().all(|dependency| {
+            loader
+                .modules
+                .iter()
+                .any(|other| other.manifest.namespace == dependency.namespace && other.applied)
+        });
+        if !dependencies_applied {
+            continue;
+        }
+        let handles: Vec<UntypedHandle> = loader
+            .files
+            .iter()
+            .filter(|handle| {
+                handle
+                    .path()
+                    .is_some_and(|path| module.contains(&path.to_string()))
+            })
+            .cloned()
+            .collect();
+        let manifest = module.manifest.clone();
+        let namespace = &manifest.namespace;
+        loader.modules[index].applied = true;
+
+        let mut counts: HashMap<ContentKind, usize> = HashMap::new();
+        let mut errors = Vec::new();
+        for handle in handles {
+            let path = handle
+                .path()
+                .map(|path| path.to_string())
+                .unwrap_or_default();
+            let Some(file) = apply_definition_file(
+                &mut loader,
+                &assets,
+                &mut registries,
+                &manifest,
+                handle.id(),
+                false,
+            ) else {
+                continue;
+            };
+            *counts.entry(file.kind).or_default() += file.applied;
+            errors.extend(
+                file.errors
+                    .into_iter()
+                    .map(|error| format!("'{}': {}", path, error)),
+            );
+        }
+
+        for error in &errors {
+            report_module_error(&mut toasts, namespace, error);
+        }
+        toasts.write(ToastMessage {
+            content: format!(
+                "Loaded module '{}' {}: {} buildings, {} commands, {} control panels, {} terrain kinds, {} units{}",
+                namespace,
+                manifest.version,
+                counts.get(&ContentKind::Building).unwrap_or(&0),
+                counts.get(&ContentKind::Command).unwrap_or(&0),
+                counts.get(&ContentKind::ControlPanel).unwrap_or(&0),
+                counts.get(&ContentKind::Terrain).unwrap_or(&0),
+                counts.get(&ContentKind::Unit).unwrap_or(&0),
+                if errors.is_empty() {
+                    String::new()
+                } else {
+                    format!(", {} errors", errors.len())
+                }
+            ),
+        });
+    }
+}
+
+/// Change events of the definition assets content modules consist of.
+#[derive(SystemParam)]
+struct DefinitionEvents<'w, 's> {
+    buildings: MessageReader<'w, 's, AssetEvent<BuildingDefinition>>,
+    commands: MessageReader<'w, 's, AssetEvent<CommandDefinitions>>,
+    control_panels: MessageReader<'w, 's, AssetEvent<ControlPanelDefinitions>>,
+    terrain: MessageReader<'w, 's, AssetEvent<TerrainDefinitions>>,
+    units: MessageReader<'w, 's, AssetEvent<UnitDefinition>>,
+}
+
+impl DefinitionEvents<'_, '_> {
+    /// Ids of all definition assets that were changed since the last call.
+    fn changed(&mut self) -> Vec<UntypedAssetId> {
+        fn changed_id<A: Asset>(event: &AssetEvent<A>) -> Option<UntypedAssetId> {
+            match event {
+                // a file that failed to load initially is added once it was fixed
+                AssetEvent::Added { id } | AssetEvent::Modified { id } => Some(id.untyped()),
+                _ => None,
+            }
+        }
+        let mut changed: Vec<UntypedAssetId> = self
+            .buildings
+            .read()
+            .filter_map(changed_id)
+            .chain(self.commands.read().filter_map(changed_id))
+            .chain(self.control_panels.read().filter_map(changed_id))
+            .chain(self.terrain.read().filter_map(changed_id))
+            .chain(self.units.read().filter_map(changed_id))
+            .collect();
+        changed.dedup();
+        changed
+    }
+}
+
+/// Re-applies definition files of already applied modules after they changed on disk.
+///
+/// Definitions that became invalid are reported and leave the registries untouched.
+/// Files that no longer parse are reported by `report_failed_files` and keep their
+/// previous content.
+fn reload_changed_definitions(
+    mut loader: ResMut<ModuleLoader>,
+    asset_server: Res<AssetServer>,
+    mut events: DefinitionEvents,
+    assets: ContentAssets,
+    mut registries: ContentRegistries,
+    mut toasts: MessageWriter<ToastMessage>,
+) {
+    for id in events.changed() {
+        let Some(path) = asset_server.get_path(id).map(|path| path.to_string()) else {
+            continue;
+        };
+        let Some(module) = loader.module_for_path(&path) else {
+            continue;
+        };
+        // the initial load of a module is handled by `apply_loaded_modules`
+        if !module.applied {
+            continue;
+        }
+        let manifest = module.manifest.clone();
+        let Some(file) =
+            apply_definition_file(&mut loader, &assets, &mut registries, &manifest, id, true)
+        else {
+            continue;
+        };
+        for error in &file.errors {
+            report_module_error(
+                &mut toasts,
+                &manifest.namespace,
+                &format!("reloading '{}': {}", path, error),
+            );
+        }
+        info!("Reloaded '{}' of module '{}'", path, manifest.namespace);
+        toasts.write(ToastMessage {
+            content: format!(
+                "Reloaded '{}': {} {} definitions{}",
+                path,
+                file.applied,
+                file.kind,
+                if file.errors.is_empty() {
+                    String::new()
+                } else {
+                    format!(", {} errors", file.errors.len())
+                }
+            ),
+        });
+    }
+}
+
+pub struct ModuleLoaderPlugin;
+
+impl Plugin for ModuleLoaderPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<ModuleManifest>()
+            // files with a more specific extension, e.g. `building.ron`, use their own loader
+            .register_asset_loader(RonAssetLoader::<ModuleManifest>::new(&["ron"]))
+            .init_resource::<ModuleLoader>()
+            .add_systems(Startup, load_modules_folder)
+            .add_systems(
+                Update,
+                (
+                    discover_modules,
+                    report_failed_files,
+                    reload_changed_definitions,
+                    apply_loaded_modules,
+                )
+                    .chain(),
+            );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(namespace: &str, version: &str, dependencies: &[(&str, &str)]) -> ModuleManifest {
+        ModuleManifest {
+            namespace: namespace.to_string(),
+            version: Version::parse(version).unwrap(),
+            dependencies: dependencies
+                .iter()
+                .map(|(namespace, requirement)| ModuleDependency {
+                    namespace: namespace.to_string(),
+                    version: VersionReq::parse(requirement).unwrap(),
+                })
+                .collect(),
+            overrides: Vec::new(),
+        }
+    }
+
+    fn discovered(manifests: Vec<ModuleManifest>) -> Vec<DiscoveredModule> {
+        manifests
+            .into_iter()
+            .map(|manifest| (manifest.namespace.clone(), manifest))
+            .collect()
+    }
+
+    /// Namespaces of the loaded modules and of the rejected modules, sorted.
+    fn resolve(manifests: Vec<ModuleManifest>) -> (Vec<String>, Vec<String>) {
+        let (order, rejected) = resolve_load_order(discovered(manifests));
+        let mut rejected: Vec<String> = rejected
+            .into_iter()
+            .map(|(namespace, _)| namespace)
+            .collect();
+        rejected.sort();
+        (
+            order
+                .into_iter()
+                .map(|(_, manifest)| manifest.namespace)
+                .collect(),
+            rejected,
+        )
+    }
+
+    #[test]
+    fn dependencies_load_first() {
+        let (order, rejected) = resolve(vec![
+            manifest("alpha", "1.0.0", &[("core", "^0.1")]),
+            manifest("core", "0.1.2", &[]),
+            manifest("beta", "0.3.0", &[]),
+        ]);
+        assert_eq!(order, ["beta", "core", "alpha"]);
+        assert!(rejected.is_empty());
+    }
+
+    #[test]
+    fn missing_dependency_rejects_dependents() {
+        let (order, rejected) = resolve(vec![
+            manifest("core", "0.1.0", &[]),
+            manifest("tanks", "0.1.0", &[("vehicles", "*")]),
+            manifest("heavy_tanks", "0.1.0", &[("tanks", "*")]),
+        ]);
+        assert_eq!(order, ["core"]);
+        assert_eq!(rejected, ["heavy_tanks", "tanks"]);
+    }
+
+    #[test]
+    fn version_requirement_mismatch_rejects_module() {
+        let (order, rejected) = resolve_load_order(discovered(vec![
+            manifest("core", "0.2.0", &[]),
+            manifest("old", "1.0.0", &[("core", "^0.1")]),
+            manifest("new", "1.0.0", &[("core", ">=0.2")]),
+        ]));
+        let order: Vec<&str> = order
+            .iter()
+            .map(|(_, manifest)| manifest.namespace.as_str())
+            .collect();
+        assert_eq!(order, ["core", "new"]);
+        assert_eq!(rejected.len(), 1);
+        let (namespace, problem) = &rejected[0];
+        assert_eq!(namespace, "old");
+        assert!(problem.contains("^0.1"), "unexpected problem: {}", problem);
+    }
+
+    #[test]
+    fn cycles_reject_members_and_dependents() {
+        let (order, rejected) = resolve(vec![
+            manifest("core", "0.1.0", &[]),
+            manifest("ping", "0.1.0", &[("pong", "*")]),
+            manifest("pong", "0.1.0", &[("ping", "*"), ("core", "*")]),
+            manifest("spectator", "0.1.0", &[("ping", "*")]),
+        ]);
+        assert_eq!(order, ["core"]);
+        assert_eq!(rejected, ["ping", "pong", "spectator"]);
+    }
+
+    #[test]
+    fn overrides_need_a_dependency_that_defines_the_id() {
+        let core = manifest("core", "0.1.0", &[]);
+        let mut tanks = manifest("tanks", "0.1.0", &[("core", "*")]);
+        tanks.overrides = vec!["core:barracks".to_string(), "core:factory".to_string()];
+        let mut rogue = manifest("rogue", "0.1.0", &[]);
+        rogue.overrides = vec!["core:barracks".to_string()];
+
+        let mut loader = ModuleLoader::default();
+        let kind = ContentKind::Building;
+        assert_eq!(
+            loader.claim(kind, "core:barracks", &core, false),
+            Ok(Claim::New)
+        );
+        // not a dependency of the module that defined it
+        assert!(loader.claim(kind, "core:barracks", &rogue, false).is_err());
+        // not defined by any module
+        assert!(loader.claim(kind, "core:factory", &tanks, false).is_err());
+        assert_eq!(
+            loader.claim(kind, "core:barracks", &tanks, false),
+            Ok(Claim::Override {
+                previous_owner: "core".to_string()
+            })
+        );
+        // the overridden id belongs to the overriding module now
+        assert_eq!(
+            loader.claim(kind, "core:barracks", &tanks, true),
+            Ok(Claim::Reload)
+        );
+        assert!(loader.claim(kind, "core:barracks", &core, true).is_err());
+    }
+
+    #[test]
+    fn ids_must_be_new_and_inside_the_namespace() {
+        let core = manifest("core", "0.1.0", &[]);
+        let tanks = manifest("tanks", "0.1.0", &[("core", "*")]);
+
+        let mut loader = ModuleLoader::default();
+        let kind = ContentKind::Unit;
+        assert!(loader.claim(kind, "core:infantry", &core, false).is_ok());
+        assert!(loader.claim(kind, "core:infantry", &core, false).is_err());
+        assert!(loader.claim(kind, "core:infantry", &tanks, false).is_err());
+        assert!(loader.claim(kind, "tanks:", &tanks, false).is_err());
+        assert!(loader.claim(kind, "tank", &tanks, false).is_err());
+        // ids are separate per kind of content
+        assert!(
+            loader
+                .claim(ContentKind::Building, "core:infantry", &core, false)
+                .is_ok()
+        );
+    }
+}
@@ -0,0 +1,536 @@
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+
+use bevy::prelude::*;
+
+use crate::{
+    AppState, InputMode, MouseCursor,
+    debug_overlay::DebugOverlays,
+    flow_field::FlowFieldTarget,
+    map::{FIELD_SIZE, Map},
+    terrain::TerrainRegistry,
+};
+
+/// Upper bound of tiles a single search expands before it gives up,
+/// so unreachable goals do not scan every loaded chunk.
+const MAX_EXPANDED_TILES: usize = 16_384;
+
+/// Cost of moving onto the tile at `global_pos`,
+/// or `None` if the tile is occupied, not walkable or not loaded.
+pub fn movement_cost(map: &Map, terrain: &TerrainRegistry, global_pos: IVec2) -> Option<f32> {
+    if map.occupant_at(global_pos).is_some() {
+        return None;
+    }
+    let kind = terrain.get(map.terrain_at(global_pos)?);
+    kind.walkable.then_some(kind.movement_cost)
+}
+
+/// Tile in the open set of a search, ordered so the lowest estimate is popped first
+/// from a [`BinaryHeap`].
+pub struct OpenTile {
+    pub estimate: f32,
+    pub tile: IVec2,
+}
+
+impl PartialEq for OpenTile {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for OpenTile {}
+
+impl PartialOrd for OpenTile {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OpenTile {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.estimate.total_cmp(&self.estimate)
+    }
+}
+
+/// Finds the cheapest path from `start` to `goal` across the loaded chunks with A*,
+/// moving between orthogonally neighboring tiles.
+///
+/// The start tile itself does not need to be walkable, so entities standing on blocked
+/// tiles can still leave them.
+///
+/// # Returns
+/// - The global tile positions of the path, from `start` to `goal` inclusive.
+/// - `None` if the goal cannot be reached.
+pub fn find_path(
+    map: &Map,
+    terrain: &TerrainRegistry,
+    start: IVec2,
+    goal: IVec2,
+) -> Option<Vec<IVec2>> {
+    if start != goal && movement_cost(map, terrain, goal).is_none() {
+        return None;
+    }
+    let min_cost = terrain.min_movement_cost();
+    let heuristic = |tile: IVec2| (goal - tile).abs().element_sum() as f32 * min_cost;
+
+    let mut open = BinaryHeap::from([OpenTile {
+        estimate: heuristic(start),
+        tile: start,
+    }]);
+    let mut costs = HashMap::from([(start, 0.0)]);
+    let mut came_from = HashMap::new();
+    let mut closed = HashSet::new();
+    while let Some(OpenTile { tile, .. }) = open.pop() {
+        if tile == goal {
+            let mut path = vec![goal];
+            while let Some(&previous) = came_from.get(path.last().unwrap()) {
+                path.push(previous);
+            }
+            path.reverse();
+            return Some(path);
+        }
+        if !closed.insert(tile) {
+            continue;
+        }
+        if closed.len() > MAX_EXPANDED_TILES {
+            return None;
+        }
+        let cost = costs[&tile];
+        for direction in [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y] {
+            let neighbor = tile + direction;
+            let Some(step_cost) = movement_cost(map, terrain, neighbor) else {
+                continue;
+            };
+            let neighbor_cost = cost + step_cost;
+            if costs
+                .get(&neighbor)
+                .is_some_and(|&known| known <= neighbor_cost)
+            {
+                continue;
+            }
+            costs.insert(neighbor, neighbor_cost);
+            came_from.insert(neighbor, tile);
+            open.push(OpenTile {
+                estimate: neighbor_cost + heuristic(neighbor),
+                tile: neighbor,
+            });
+        }
+    }
+    None
+}
+
+/// Tile an entity wants to reach. Its [`Path`] is computed from the tile it stands on.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct PathTarget {
+    pub goal: IVec2,
+}
+
+/// Path computed for a [`PathTarget`].
+///
+/// Removed when a building is placed on or removed near the path, and recomputed then.
+#[derive(Component, Debug, Clone, Default)]
+pub struct Path {
+    /// Global tile positions from the start to the goal, empty if the goal is unreachable.
+    pub tiles: Vec<IVec2>,
+}
+
+/// Computes a [`Path`] for every [`PathTarget`] that has none.
+pub(crate) fn compute_paths(
+    mut commands: Commands,
+    map: Res<Map>,
+    terrain: Res<TerrainRegistry>,
+    targets: Query<(Entity, &PathTarget, &Transform), Without<Path>>,
+) {
+    for (entity, target, transform) in &targets {
+        let start = Map::world_to_global(transform.translation.truncate());
+        let tiles = find_path(&map, &terrain, start, target.goal).unwrap_or_default();
+        commands.entity(entity).insert(Path { tiles });
+    }
+}
+
+/// Drops paths affected by changes of the map since the last run,
+/// and every path when the terrain registry changed.
+///
+/// A path is affected if one of its tiles is now occupied or lies in a chunk that was
+/// loaded or unloaded, or if a freed tile lies next to its bounding box and may open
+/// a shortcut. Unreachable goals are retried whenever the [`Map::navigation_revision`]
+/// changed, e.g. because the chunk of the goal was loaded.
+pub(crate) fn invalidate_paths(
+    mut commands: Commands,
+    mut map: ResMut<Map>,
+    terrain: Res<TerrainRegistry>,
+    paths: Query<(Entity, &Path)>,
+    mut last_revision: Local<u64>,
+) {
+    let changed_tiles = map.take_changed_tiles();
+    let streamed_chunks = map.take_streamed_chunks();
+    let revision_changed = *last_revision != map.navigation_revision();
+    *last_revision = map.navigation_revision();
+    if !revision_changed && !terrain.is_changed() {
+        return;
+    }
+    let (occupied, freed): (Vec<IVec2>, Vec<IVec2>) = changed_tiles
+        .into_iter()
+        .partition(|&tile| map.occupant_at(tile).is_some());
+    for (entity, path) in &paths {
+        let affected = terrain.is_changed()
+            || path.tiles.iter().any(|tile| {
+                occupied.contains(tile) || streamed_chunks.contains(&Map::global_to_chunk(*tile).0)
+            })
+            || match path.tiles.iter().copied().reduce(IVec2::min) {
+                None => true,
+                Some(min) => {
+                    let max = path.tiles.iter().copied().reduce(IVec2::max).unwrap();
+                    let bounds = IRect::from_corners(min, max).inflate(1);
+                    freed.iter().any(|&tile| bounds.contains(tile))
+                }
+            };
+        if affected {
+            commands.entity(entity).remove::<Path>();
+        }
+    }
+}
+
+/// Entity whose path and flow field are shown by the pathfinding overlay,
+/// set with the mouse.
+#[derive(Component)]
+struct PathProbe;
+
+type PathProbeData<'a> = (
+    Entity,
+    &'a mut Transform,
+    &'a mut PathTarget,
+    &'a mut FlowFieldTarget,
+);
+
+/// While the pathfinding overlay is enabled, right-click sets the start
+/// and shift + right-click the goal of the [`PathProbe`].
+fn place_path_probe(
+    mut commands: Commands,
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    cursor: Res<MouseCursor>,
+    probe: Option<Single<PathProbeData, With<PathProbe>>>,
+) {
+    if !mouse_input.just_pressed(MouseButton::Right) {
+        return;
+    }
+    let Some(world_pos) = cursor.world_position() else {
+        return;
+    };
+    let tile = Map::world_to_global(world_pos);
+    let set_goal = keyboard_input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    let Some(probe) = probe else {
+        commands.spawn((
+            PathProbe,
+            PathTarget { goal: tile },
+            FlowFieldTarget { goal: tile },
+            Transform::from_translation(Map::tile_center(tile).extend(0.0)),
+        ));
+        return;
+    };
+    let (entity, mut transform, mut target, mut flow_field_target) = probe.into_inner();
+    if set_goal {
+        target.goal = tile;
+        flow_field_target.goal = tile;
+    } else {
+        transform.translation = Map::tile_center(tile).extend(0.0);
+    }
+    commands.entity(entity).remove::<Path>();
+}
+
+fn remove_path_probe(mut commands: Commands, probes: Query<Entity, With<PathProbe>>) {
+    for probe in &probes {
+        commands.entity(probe).despawn();
+    }
+}
+
+/// Draws every computed path, and a cross on the goal of unreachable targets.
+fn debug_paths(mut gizmos: Gizmos, paths: Query<(&Path, &PathTarget)>) {
+    let color_path = Color::srgba(1.0, 0.9, 0.1, 0.9);
+    let color_unreachable = Color::srgba(1.0, 0.1, 0.1, 0.9);
+    for (path, target) in &paths {
+        if path.tiles.is_empty() {
+            let center = Map::tile_center(target.goal);
+            let corner = Vec2::splat(FIELD_SIZE * 0.4);
+            gizmos.line_2d(center - corner, center + corner, color_unreachable);
+            gizmos.line_2d(
+                center + corner.with_x(-corner.x),
+                center - corner.with_x(-corner.x),
+                color_unreachable,
+            );
+            continue;
+        }
+        gizmos.linestrip_2d(
+            path.tiles.iter().map(|&tile| Map::tile_center(tile)),
+            color_path,
+        );
+        gizmos.circle_2d(
+            Isometry2d::from_translation(Map::tile_center(target.goal)),
+            FIELD_SIZE / 3.0,
+            color_path,
+        );
+    }
+}
+
+pub struct PathfindingPlugin;
+
+impl Plugin for PathfindingPlugin {
+    fn build(&self, app: &mut App) {
+        let overlay_enabled = |overlays: Res<DebugOverlays>| overlays.pathfinding;
+        app.add_systems(
+            Update,
+            (
+                place_path_probe.run_if(in_state(InputMode::Normal).and(overlay_enabled)),
+                remove_path_probe.run_if(not(overlay_enabled)),
+                invalidate_paths,
+                compute_paths,
+                debug_paths.run_if(overlay_enabled),
+            )
+                .chain()
+                .run_if(in_state(AppState::Game)),
+        );
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use bevy::ecs::{system::RunSystemOnce, world::CommandQueue};
+
+    use super::*;
+    use crate::{
+        map::{CHUNK_SIZE, CHUNK_SIZE_I32},
+        terrain::{TerrainId, TerrainKind},
+    };
+
+    /// Terrain kinds used by [`test_map`]: walkable grass, slow mud and impassable water.
+    pub(crate) fn test_terrain(mud_cost: f32) -> TerrainRegistry {
+        let mut terrain = TerrainRegistry::default();
+        for (id, walkable, movement_cost) in [
+            ("test:grass", true, 1.0),
+            ("test:mud", true, mud_cost),
+            ("test:water", false, 1.0),
+        ] {
+            terrain
+                .register(TerrainKind {
+                    id: id.to_string(),
+                    color: (0.0, 0.0, 0.0),
+                    buildable: true,
+                    walkable,
+                    movement_cost,
+                    blocks_vision: false,
+                })
+                .unwrap();
+        }
+        terrain
+    }
+
+    /// Map with the given chunks loaded, the terrain of every tile named by `terrain_at`,
+    /// and a one tile building on each of the `occupied` tiles.
+    pub(crate) fn test_map(
+        terrain: &TerrainRegistry,
+        chunks: &[IVec2],
+        terrain_at: impl Fn(IVec2) -> &'static str,
+        occupied: &[IVec2],
+    ) -> Map {
+        let mut world = World::new();
+        let occupant = world.spawn_empty().id();
+        let mut map = Map::default();
+        for &chunk_pos in chunks {
+            load_test_chunk(&mut map, terrain, chunk_pos, &terrain_at);
+        }
+        for &tile in occupied {
+            map.try_place(tile, &[IVec2::ZERO], occupant, terrain)
+                .unwrap();
+        }
+        map
+    }
+
+    /// Loads the chunk at `chunk_pos` with the terrain of every tile named by `terrain_at`.
+    pub(crate) fn load_test_chunk(
+        map: &mut Map,
+        terrain: &TerrainRegistry,
+        chunk_pos: IVec2,
+        terrain_at: impl Fn(IVec2) -> &'static str,
+    ) {
+        let world = World::new();
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &world);
+        map.load_chunk(
+            chunk_pos,
+            |chunk_pos| {
+                std::array::from_fn(|x| {
+                    std::array::from_fn(|y| {
+                        let tile = Map::chunk_to_global(chunk_pos, IVec2::new(x as i32, y as i32));
+                        terrain.id(terrain_at(tile)).unwrap()
+                    })
+                })
+            },
+            &mut commands,
+        );
+    }
+
+    pub(crate) fn grass(_: IVec2) -> &'static str {
+        "test:grass"
+    }
+
+    /// Checks that `path` leads from `start` to `goal` in orthogonal steps over walkable tiles.
+    fn assert_walkable(
+        map: &Map,
+        terrain: &TerrainRegistry,
+        path: &[IVec2],
+        start: IVec2,
+        goal: IVec2,
+    ) {
+        assert_eq!(path.first(), Some(&start));
+        assert_eq!(path.last(), Some(&goal));
+        for pair in path.windows(2) {
+            assert_eq!((pair[1] - pair[0]).abs().element_sum(), 1, "{:?}", pair);
+            assert!(
+                movement_cost(map, terrain, pair[1]).is_some(),
+                "{} is blocked",
+                pair[1]
+            );
+        }
+    }
+
+    #[test]
+    fn detours_around_occupied_tiles() {
+        let terrain = test_terrain(1.0);
+        // a wall at x = 5 with a gap at the top of the chunk
+        let wall: Vec<IVec2> = (0..12).map(|y| IVec2::new(5, y)).collect();
+        let map = test_map(&terrain, &[IVec2::ZERO], grass, &wall);
+        let (start, goal) = (IVec2::new(2, 2), IVec2::new(8, 2));
+
+        let path = find_path(&map, &terrain, start, goal).unwrap();
+        assert_walkable(&map, &terrain, &path, start, goal);
+        // up to the gap at y = 12, across and back down
+        assert_eq!(path.len(), 10 + 6 + 10 + 1);
+    }
+
+    #[test]
+    fn respects_terrain_cost() {
+        let mud_patch = |tile: IVec2| {
+            if tile.x == 5 && tile.y < 4 {
+                "test:mud"
+            } else {
+                "test:grass"
+            }
+        };
+        let (start, goal) = (IVec2::new(2, 1), IVec2::new(8, 1));
+
+        // crossing the patch costs 6 + 10, going around it 12
+        let terrain = test_terrain(10.0);
+        let map = test_map(&terrain, &[IVec2::ZERO], mud_patch, &[]);
+        let path = find_path(&map, &terrain, start, goal).unwrap();
+        assert_walkable(&map, &terrain, &path, start, goal);
+        assert!(path.iter().all(|&tile| mud_patch(tile) != "test:mud"));
+        assert_eq!(path.len(), 13);
+
+        // crossing the patch costs 6 + 2, so the straight line is cheaper
+        let terrain = test_terrain(2.0);
+        let map = test_map(&terrain, &[IVec2::ZERO], mud_patch, &[]);
+        let path = find_path(&map, &terrain, start, goal).unwrap();
+        assert_walkable(&map, &terrain, &path, start, goal);
+        assert_eq!(path.len(), 7);
+    }
+
+    #[test]
+    fn crosses_chunk_boundaries() {
+        let terrain = test_terrain(1.0);
+        let chunks = [IVec2::ZERO, IVec2::new(1, 0), IVec2::new(1, 1)];
+        let map = test_map(&terrain, &chunks, grass, &[]);
+        let start = IVec2::new(2, 2);
+        let goal = IVec2::new(CHUNK_SIZE_I32 + 4, CHUNK_SIZE_I32 + 4);
+
+        let path = find_path(&map, &terrain, start, goal).unwrap();
+        assert_walkable(&map, &terrain, &path, start, goal);
+        assert_eq!(path.len(), (goal - start).element_sum() as usize + 1);
+        // the chunk at (0, 1) is not loaded, so the path has to go through (1, 0)
+        assert!(
+            path.iter()
+                .all(|&tile| Map::global_to_chunk(tile).0 != IVec2::new(0, 1))
+        );
+    }
+
+    #[test]
+    fn unreachable_goals_have_no_path() {
+        let terrain = test_terrain(1.0);
+        let goal = IVec2::new(8, 8);
+        let enclosure =
+            [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y].map(|offset| goal + offset);
+        let lake = |tile: IVec2| {
+            if tile == IVec2::new(3, 3) {
+                "test:water"
+            } else {
+                "test:grass"
+            }
+        };
+        let map = test_map(&terrain, &[IVec2::ZERO], lake, &enclosure);
+        let start = IVec2::new(1, 1);
+
+        assert_eq!(find_path(&map, &terrain, start, goal), None);
+        // occupied, not walkable and not loaded goals
+        assert_eq!(find_path(&map, &terrain, start, enclosure[0]), None);
+        assert_eq!(find_path(&map, &terrain, start, IVec2::new(3, 3)), None);
+        assert_eq!(find_path(&map, &terrain, start, IVec2::new(-4, 1)), None);
+    }
+
+    #[test]
+    fn paths_to_goals_in_chunks_loaded_later_are_found() {
+        let terrain = test_terrain(1.0);
+        let mut world = World::new();
+        world.insert_resource(test_map(&terrain, &[IVec2::ZERO], grass, &[]));
+        world.insert_resource(terrain);
+        let goal = IVec2::new(CHUNK_SIZE_I32 + 4, 2);
+        let unit = world
+            .spawn((
+                PathTarget { goal },
+                Transform::from_translation(Map::tile_center(IVec2::new(2, 2)).extend(0.0)),
+            ))
+            .id();
+
+        world.run_system_once(invalidate_paths).unwrap();
+        world.run_system_once(compute_paths).unwrap();
+        assert!(world.get::<Path>(unit).unwrap().tiles.is_empty());
+
+        world.resource_scope(|world, mut map: Mut<Map>| {
+            let terrain = world.resource::<TerrainRegistry>();
+            load_test_chunk(&mut map, terrain, IVec2::new(1, 0), grass);
+        });
+        world.run_system_once(invalidate_paths).unwrap();
+        world.run_system_once(compute_paths).unwrap();
+        let path = &world.get::<Path>(unit).unwrap().tiles;
+        assert_eq!(path.last(), Some(&goal));
+    }
+
+    #[test]
+    fn paths_through_unloaded_chunks_are_dropped() {
+        let terrain = test_terrain(1.0);
+        let chunks = [IVec2::ZERO, IVec2::new(1, 0)];
+        let mut world = World::new();
+        world.insert_resource(test_map(&terrain, &chunks, grass, &[]));
+        world.insert_resource(terrain);
+        let goal = IVec2::new(CHUNK_SIZE_I32 + 4, 2);
+        let unit = world
+            .spawn((
+                PathTarget { goal },
+                Transform::from_translation(Map::tile_center(IVec2::new(2, 2)).extend(0.0)),
+            ))
+            .id();
+        world.run_system_once(invalidate_paths).unwrap();
+        world.run_system_once(compute_paths).unwrap();
+        assert_eq!(world.get::<Path>(unit).unwrap().tiles.last(), Some(&goal));
+
+        world.resource_scope(|world, mut map: Mut<Map>| {
+            let mut queue = CommandQueue::default();
+            let mut commands = Commands::new(&mut queue, world);
+            let generated = [[TerrainId::default(); CHUNK_SIZE]; CHUNK_SIZE];
+            map.unload_chunk(IVec2::new(1, 0), |_| generated, &mut commands);
+        });
+        world.run_system_once(invalidate_paths).unwrap();
+        assert!(!world.entity(unit).contains::<Path>());
+    }
+}
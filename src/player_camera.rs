@@ -1,6 +1,6 @@
 use bevy::{input::mouse::MouseWheel, prelude::*};
 
-use crate::AppState;
+use crate::{AppState, key_bindings};
 
 #[derive(Component)]
 pub struct PlayerCamera {
@@ -25,6 +25,17 @@ impl PlayerCamera {
     const MAX_SCALE: f32 = 1.0;
     const SCALE_STEP: f32 = 0.05;
     const SCALE_INTERPOLATION_FACTOR: f32 = 0.1;
+
+    /// Position and scale the camera is moving towards.
+    pub fn target_view(&self) -> (Vec2, f32) {
+        (self.target_position, self.target_scale)
+    }
+
+    /// Moves the camera towards the given position and scale.
+    pub fn set_target_view(&mut self, position: Vec2, scale: f32) {
+        self.target_position = position;
+        self.target_scale = scale.clamp(Self::MIN_SCALE, Self::MAX_SCALE);
+    }
 }
 
 fn setup(mut commands: Commands) {
@@ -69,16 +80,16 @@ fn controls(
     // --- Movement Controls ---
     let mut direction = Vec2::ZERO;
 
-    if keyboard_input.pressed(KeyCode::KeyW) {
+    if keyboard_input.pressed(key_bindings::CAMERA_UP) {
         direction.y += 1.0;
     }
-    if keyboard_input.pressed(KeyCode::KeyS) {
+    if keyboard_input.pressed(key_bindings::CAMERA_DOWN) {
         direction.y -= 1.0;
     }
-    if keyboard_input.pressed(KeyCode::KeyA) {
+    if keyboard_input.pressed(key_bindings::CAMERA_LEFT) {
         direction.x -= 1.0;
     }
-    if keyboard_input.pressed(KeyCode::KeyD) {
+    if keyboard_input.pressed(key_bindings::CAMERA_RIGHT) {
         direction.x += 1.0;
     }
 
@@ -0,0 +1,413 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, RwLock},
+};
+
+use bevy::prelude::*;
+
+use crate::{
+    AppState,
+    flow_field::FlowFieldTarget,
+    map::{FIELD_SIZE, Map},
+    terrain::TerrainRegistry,
+    toasts::ToastMessage,
+    units::{Follow, UnitRegistry, free_tile_next_to},
+    user_controls::{
+        CommandDispatcher, CommandDispatcherPipeline, CommandEvent, CommandPayload,
+        ControlPanelState, impl_command_dispatcher,
+    },
+};
+
+/// Credits the player starts a new game with.
+pub const STARTING_CREDITS: u32 = 500;
+
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct PlayerResources {
+    pub credits: u32,
+}
+
+impl Default for PlayerResources {
+    fn default() -> Self {
+        Self {
+            credits: STARTING_CREDITS,
+        }
+    }
+}
+
+/// Settings shared by all production buildings.
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct ProductionSettings {
+    /// Number of units a building can have queued, including the one in training.
+    pub max_queue_length: usize,
+}
+
+impl Default for ProductionSettings {
+    fn default() -> Self {
+        Self {
+            max_queue_length: 5,
+        }
+    }
+}
+
+/// Unit waiting in a [`ProductionQueue`].
+/// Cost and build time are taken from the registry when the unit is queued.
+#[derive(Debug, Clone)]
+pub struct QueuedUnit {
+    /// Registry id of the unit.
+    pub id: String,
+    /// Credits paid for the unit, refunded when it is canceled.
+    pub cost: u32,
+    /// Training time in seconds.
+    pub build_time: f32,
+}
+
+/// Units a building trains one after another.
+#[derive(Component, Debug, Clone, Default)]
+pub struct ProductionQueue {
+    /// Queued units, the first one is in training.
+    pub items: VecDeque<QueuedUnit>,
+    /// Seconds the first unit has been in training.
+    pub progress: f32,
+}
+
+impl ProductionQueue {
+    /// Share of the training of the first unit that is done, from 0 to 1.
+    pub fn completion(&self) -> Option<f32> {
+        let item = self.items.front()?;
+        Some(if item.build_time > 0.0 {
+            (self.progress / item.build_time).min(1.0)
+        } else {
+            1.0
+        })
+    }
+}
+
+/// Where units trained by a building head to once they were spawned.
+#[derive(Component, Debug, Clone, Copy)]
+pub enum RallyPoint {
+    /// Move to the world position.
+    Point(Vec2),
+    /// Follow the entity, e.g. to gather next to another building.
+    Entity(Entity),
+}
+
+#[derive(Message, Debug, Clone)]
+pub enum ProductionRequest {
+    /// Queues the unit at the building and pays its cost.
+    Train { building: Entity, unit: String },
+    /// Removes the last queued unit from the building and refunds its cost.
+    Cancel { building: Entity },
+    /// Sets the rally point of the building, `None` removes it.
+    SetRally {
+        building: Entity,
+        rally: Option<RallyPoint>,
+    },
+}
+
+/// Train commands of the registered units, mapped to the id of the unit they train.
+/// Shared with the [`TrainCommandDispatcher`] and kept in sync with the [`UnitRegistry`].
+#[derive(Resource, Debug, Clone, Default)]
+pub struct TrainCommands(Arc<RwLock<HashMap<String, String>>>);
+
+impl TrainCommands {
+    fn unit(&self, command_type: &str) -> Option<String> {
+        self.0.read().ok()?.get(command_type).cloned()
+    }
+}
+
+/// Queues the unit whose definition declares the dispatched train command.
+#[derive(Debug)]
+struct TrainCommandDispatcher {
+    train_commands: TrainCommands,
+}
+
+impl CommandDispatcher for TrainCommandDispatcher {
+    fn catches(&self, command_type: &str) -> bool {
+        self.train_commands.unit(command_type).is_some()
+    }
+
+    fn dispatch_command(&self, command_event: CommandEvent, commands: &mut Commands) {
+        let Some(unit) = self.train_commands.unit(&command_event.command_type) else {
+            return;
+        };
+        for &building in &command_event.issuers {
+            commands.write_message(ProductionRequest::Train {
+                building,
+                unit: unit.clone(),
+            });
+        }
+    }
+}
+
+fn setup_production_commands(
+    mut dispatcher_pipeline: ResMut<CommandDispatcherPipeline>,
+    train_commands: Res<TrainCommands>,
+) {
+    dispatcher_pipeline.register_dispatcher(Box::new(TrainCommandDispatcher {
+        train_commands: train_commands.clone(),
+    }));
+    let production_dispatcher = impl_command_dispatcher!(
+        "ProductionCommandDispatcher",
+        ["core:cancel_training", "core:set_rally"],
+        |event: CommandEvent, commands: &mut Commands| {
+            for &building in &event.issuers {
+                let request = match event.command_type.as_str() {
+                    "core:cancel_training" => ProductionRequest::Cancel { building },
+                    "core:set_rally" => ProductionRequest::SetRally {
+                        building,
+                        rally: match event.payload {
+                            CommandPayload::TargetPoint(point) => Some(RallyPoint::Point(point)),
+                            // targeting the building itself removes its rally point
+                            CommandPayload::TargetEntity(target) if target != building => {
+                                Some(RallyPoint::Entity(target))
+                            }
+                            _ => None,
+                        },
+                    },
+                    _ => continue,
+                };
+                commands.write_message(request);
+            }
+        },
+    );
+    dispatcher_pipeline.register_dispatcher(production_dispatcher);
+}
+
+/// Rebuilds the [`TrainCommands`] after units were registered or replaced.
+fn sync_train_commands(registry: Res<UnitRegistry>, train_commands: Res<TrainCommands>) {
+    let Ok(mut map) = train_commands.0.write() else {
+        return;
+    };
+    map.clear();
+    for (id, entry) in &registry.units {
+        let Some(command) = &entry.train_command else {
+            continue;
+        };
+        if let Some(other) = map.insert(command.clone(), id.clone()) {
+            warn!(
+                "Units '{}' and '{}' are both trained by '{}'",
+                other, id, command
+            );
+        }
+    }
+}
+
+fn handle_production_requests(
+    mut commands: Commands,
+    mut requests: MessageReader<ProductionRequest>,
+    registry: Res<UnitRegistry>,
+    settings: Res<ProductionSettings>,
+    mut resources: ResMut<PlayerResources>,
+    mut queues: Query<&mut ProductionQueue>,
+    mut toasts: MessageWriter<ToastMessage>,
+) {
+    for request in requests.read() {
+        match request {
+            ProductionRequest::Train { building, unit } => {
+                let Ok(mut queue) = queues.get_mut(*building) else {
+                    warn!(
+                        "Cannot train '{}' at {}, it has no production queue",
+                        unit, building
+                    );
+                    continue;
+                };
+                let Some(entry) = registry.units.get(unit) else {
+                    warn!("Cannot train unknown unit '{}'", unit);
+                    continue;
+                };
+                let content = if queue.items.len() >= settings.max_queue_length {
+                    format!(
+                        "Cannot train '{}', the queue is full ({} units)",
+                        unit, settings.max_queue_length
+                    )
+                } else if resources.credits < entry.cost {
+                    format!(
+                        "Cannot train '{}', it costs {} credits but only {} are left",
+                        unit, entry.cost, resources.credits
+                    )
+                } else {
+                    resources.credits -= entry.cost;
+                    queue.items.push_back(QueuedUnit {
+                        id: unit.clone(),
+                        cost: entry.cost,
+                        build_time: entry.build_time,
+                    });
+                    continue;
+                };
+                toasts.write(ToastMessage { content });
+            }
+            ProductionRequest::Cancel { building } => {
+                let Ok(mut queue) = queues.get_mut(*building) else {
+                    continue;
+                };
+                let Some(item) = queue.items.pop_back() else {
+                    continue;
+                };
+                if queue.items.is_empty() {
+                    queue.progress = 0.0;
+                }
+                resources.credits += item.cost;
+            }
+            ProductionRequest::SetRally { building, rally } => {
+                if !queues.contains(*building) {
+                    continue;
+                }
+                match rally {
+                    Some(rally) => commands.entity(*building).insert(*rally),
+                    None => commands.entity(*building).remove::<RallyPoint>(),
+                };
+            }
+        }
+    }
+}
+
+/// Advances the training of the first unit of every queue and spawns it next to the
+/// building once done, sending it to the [`RallyPoint`] of the building.
+/// Finished units wait while every tile around the building is blocked.
+#[allow(clippy::too_many_arguments)]
+fn advance_production(
+    mut commands: Commands,
+    time: Res<Time>,
+    map: Res<Map>,
+    terrain: Res<TerrainRegistry>,
+    registry: Res<UnitRegistry>,
+    mut resources: ResMut<PlayerResources>,
+    mut queues: Query<(Entity, &mut ProductionQueue, Option<&RallyPoint>)>,
+    mut toasts: MessageWriter<ToastMessage>,
+) {
+    for (building, mut queue, rally) in &mut queues {
+        let Some(build_time) = queue.items.front().map(|item| item.build_time) else {
+            continue;
+        };
+        queue.progress = (queue.progress + time.delta_secs()).min(build_time);
+        if queue.progress < build_time {
+            continue;
+        }
+        let Some(tile) = map
+            .footprint(building)
+            .and_then(|footprint| free_tile_next_to(&map, &terrain, footprint))
+        else {
+            continue;
+        };
+        let item = queue.items.pop_front().unwrap();
+        queue.progress = 0.0;
+        let Some(unit) = registry.spawn(&item.id, Map::tile_center(tile), &mut commands) else {
+            // the unit was removed from the registry while it was queued
+            resources.credits += item.cost;
+            toasts.write(ToastMessage {
+                content: format!(
+                    "Cannot train unknown unit '{}', refunded {} credits",
+                    item.id, item.cost
+                ),
+            });
+            continue;
+        };
+        match rally {
+            Some(RallyPoint::Point(point)) => {
+                commands.entity(unit).insert(FlowFieldTarget {
+                    goal: Map::world_to_global(*point),
+                });
+            }
+            Some(RallyPoint::Entity(target)) => {
+                commands.entity(unit).insert(Follow::new(*target));
+            }
+            None => {}
+        }
+    }
+}
+
+/// Text showing the credits of the player and the queue of the building in the control panel.
+#[derive(Component)]
+struct ProductionStatus;
+
+fn setup_production_status(mut commands: Commands) {
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(10.0),
+            right: Val::Px(10.0),
+            ..Default::default()
+        },
+        Text::default(),
+        TextFont::from_font_size(14.0),
+        ProductionStatus,
+    ));
+}
+
+fn update_production_status(
+    resources: Res<PlayerResources>,
+    control_panel: Res<ControlPanelState>,
+    queues: Query<&ProductionQueue>,
+    mut status: Single<&mut Text, With<ProductionStatus>>,
+) {
+    let mut content = format!("Credits: {}", resources.credits);
+    for queue in queues.iter_many(control_panel.entities()) {
+        match (queue.items.front(), queue.completion()) {
+            (Some(item), Some(completion)) => content.push_str(&format!(
+                "\nTraining '{}' {:.0}%, {} more queued",
+                item.id,
+                completion * 100.0,
+                queue.items.len() - 1
+            )),
+            _ => content.push_str("\nNothing in training"),
+        }
+    }
+    if status.0 != content {
+        status.0 = content;
+    }
+}
+
+/// Draws a line from the buildings in the control panel to their rally point.
+fn draw_rally_points(
+    mut gizmos: Gizmos,
+    control_panel: Res<ControlPanelState>,
+    buildings: Query<(&Transform, &RallyPoint)>,
+    targets: Query<&Transform>,
+) {
+    let color = Color::srgb(0.2, 0.9, 0.4);
+    for (transform, rally) in buildings.iter_many(control_panel.entities()) {
+        let target = match rally {
+            RallyPoint::Point(point) => *point,
+            RallyPoint::Entity(target) => match targets.get(*target) {
+                Ok(target_transform) => target_transform.translation.truncate(),
+                Err(_) => continue,
+            },
+        };
+        gizmos.line_2d(transform.translation.truncate(), target, color);
+        gizmos.circle_2d(
+            Isometry2d::from_translation(target),
+            FIELD_SIZE * 0.4,
+            color,
+        );
+    }
+}
+
+pub struct ProductionPlugin;
+
+impl Plugin for ProductionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PlayerResources>()
+            .init_resource::<ProductionSettings>()
+            .init_resource::<TrainCommands>()
+            .add_message::<ProductionRequest>()
+            .add_systems(
+                Startup,
+                (setup_production_commands, setup_production_status),
+            )
+            .add_systems(
+                Update,
+                sync_train_commands.run_if(resource_changed::<UnitRegistry>),
+            )
+            .add_systems(
+                Update,
+                (
+                    handle_production_requests,
+                    advance_production,
+                    update_production_status,
+                    draw_rally_points,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::Game)),
+            );
+    }
+}
@@ -0,0 +1,913 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use bevy::{asset::io::file::FileAssetReader, ecs::system::SystemParam, prelude::*};
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    AppState, BuildingRegistry, BuildingRotation, PlacedBuilding,
+    control_groups::{ControlGroups, GROUP_COUNT},
+    key_bindings,
+    map::{CHUNK_SIZE, Map},
+    module_loader::ModuleLoader,
+    player_camera::PlayerCamera,
+    production::{PlayerResources, ProductionQueue, QueuedUnit, RallyPoint},
+    terrain::{ChunkTerrain, TerrainId, TerrainRegistry, WorldGenerator},
+    toasts::ToastMessage,
+    units::{Health, Unit, UnitRegistry},
+};
+
+mod v1;
+mod v2;
+mod v3;
+mod v4;
+mod v5;
+
+/// Version of the save format written by this build.
+/// Saves of older versions are upgraded on load, see [`upgrade_save`].
+pub const SAVE_FORMAT_VERSION: u32 = 6;
+/// Folder next to the assets folder that saves are written to.
+const SAVES_FOLDER: &str = "saves";
+const QUICKSAVE_FILE_NAME: &str = "quicksave.ron";
+
+/// Content module a save was created with.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedModule {
+    pub namespace: String,
+    pub version: Version,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedCamera {
+    pub position: (f32, f32),
+    pub scale: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedChunk {
+    pub position: (i32, i32),
+    /// Terrain of every tile as runs of `(index into SaveGame::terrain_kinds, tile count)`,
+    /// ordered by local x first and local y second.
+    pub terrain: Vec<(u16, u16)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedBuilding {
+    /// Registry id of the building, e.g. `core:barracks`.
+    pub id: String,
+    /// Global tile position of the footprint origin.
+    pub origin: (i32, i32),
+    pub rotation: BuildingRotation,
+    /// Global tile positions occupied by the building.
+    pub footprint: Vec<(i32, i32)>,
+    /// Units queued at the building, `None` for buildings that do not train units.
+    pub production: Option<SavedProduction>,
+    /// Where units trained by the building head to.
+    pub rally: Option<SavedRallyPoint>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedQueuedUnit {
+    /// Registry id of the unit.
+    pub id: String,
+    /// Credits paid for the unit.
+    pub cost: u32,
+    /// Training time in seconds.
+    pub build_time: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedProduction {
+    pub queue: Vec<SavedQueuedUnit>,
+    /// Seconds the first unit of the queue has been in training.
+    pub progress: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedUnit {
+    /// Registry id of the unit, e.g. `core:worker`.
+    pub id: String,
+    /// World position of the unit.
+    pub position: (f32, f32),
+    pub health: u32,
+}
+
+/// Member of a control group, as index into [`SaveGame::buildings`] or [`SaveGame::units`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SavedGroupMember {
+    Building(usize),
+    Unit(usize),
+}
+
+/// Rally point of a building, targets as index into [`SaveGame::buildings`] or [`SaveGame::units`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum SavedRallyPoint {
+    /// World position.
+    Point((f32, f32)),
+    Building(usize),
+    Unit(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedControlGroup {
+    /// Number key of the group.
+    pub key: u8,
+    pub members: Vec<SavedGroupMember>,
+}
+
+/// Compresses tile values into runs of `(value, count)`.
+fn encode_runs(values: impl IntoIterator<Item = u16>) -> Vec<(u16, u16)> {
+    let mut runs: Vec<(u16, u16)> = Vec::new();
+    for value in values {
+        match runs.last_mut() {
+            Some((last, count)) if *last == value && *count < u16::MAX => *count += 1,
+            _ => runs.push((value, 1)),
+        }
+    }
+    runs
+}
+
+impl SavedChunk {
+    /// Expands the terrain runs of the chunk, mapping terrain indices through `terrain_ids`.
+    fn decode(&self, terrain_ids: &[TerrainId]) -> Result<ChunkTerrain, SaveError> {
+        let invalid = |reason: String| SaveError::InvalidChunk {
+            position: self.position.into(),
+            reason,
+        };
+        let tile_count: usize = self.terrain.iter().map(|&(_, count)| count as usize).sum();
+        if tile_count != CHUNK_SIZE * CHUNK_SIZE {
+            return Err(invalid(format!(
+                "expected {} tiles, found {}",
+                CHUNK_SIZE * CHUNK_SIZE,
+                tile_count
+            )));
+        }
+        let mut chunk_terrain = [[TerrainId::default(); CHUNK_SIZE]; CHUNK_SIZE];
+        let mut tiles = chunk_terrain.iter_mut().flatten();
+        for &(index, count) in &self.terrain {
+            let terrain_id = *terrain_ids
+                .get(index as usize)
+                .ok_or_else(|| invalid(format!("terrain index {} is out of range", index)))?;
+            for tile in tiles.by_ref().take(count as usize) {
+                *tile = terrain_id;
+            }
+        }
+        Ok(chunk_terrain)
+    }
+}
+
+/// Complete state of a game as written to a save file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SaveGame {
+    /// Format version, see [`SAVE_FORMAT_VERSION`].
+    pub version: u32,
+    /// Content modules the save depends on. Loading fails if any of them is missing.
+    pub modules: Vec<SavedModule>,
+    /// Seed of the world generator, used for chunks that are not part of the save.
+    pub seed: u64,
+    pub camera: SavedCamera,
+    /// Side length of a chunk in tiles when the game was saved.
+    pub chunk_size: u16,
+    /// Registry ids of the terrain kinds referenced by [`SavedChunk::terrain`].
+    pub terrain_kinds: Vec<String>,
+    pub chunks: Vec<SavedChunk>,
+    pub buildings: Vec<SavedBuilding>,
+    pub units: Vec<SavedUnit>,
+    pub credits: u32,
+    /// Control groups that are not empty.
+    pub control_groups: Vec<SavedControlGroup>,
+}
+
+/// Leading part of every save file, read before the rest to check the format version.
+#[derive(Deserialize)]
+struct SaveHeader {
+    version: u32,
+}
+
+/// Reason why a game could not be saved or loaded.
+#[derive(Debug)]
+pub enum SaveError {
+    Io(std::io::Error),
+    Serialize(ron::Error),
+    Parse(ron::error::SpannedError),
+    /// The save was written in a format this build cannot read.
+    UnsupportedVersion {
+        found: u32,
+    },
+    /// The save was written with a chunk size that differs from the one of this build.
+    ChunkSizeMismatch {
+        found: u16,
+    },
+    /// A content module the save depends on is not loaded.
+    MissingModule {
+        namespace: String,
+        version: Version,
+    },
+    /// A content module the save depends on is loaded in an incompatible version.
+    IncompatibleModule {
+        namespace: String,
+        saved: Version,
+        loaded: Version,
+    },
+    UnknownBuilding {
+        id: String,
+    },
+    UnknownTerrain {
+        id: String,
+    },
+    UnknownUnit {
+        id: String,
+    },
+    InvalidControlGroup {
+        key: u8,
+        reason: String,
+    },
+    InvalidChunk {
+        position: IVec2,
+        reason: String,
+    },
+    InvalidRallyPoint {
+        origin: IVec2,
+        reason: String,
+    },
+}
+
+impl std::fmt::Display for SaveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "could not access save file: {}", error),
+            Self::Serialize(error) => write!(f, "could not write save: {}", error),
+            Self::Parse(error) => write!(f, "could not parse save: {}", error),
+            Self::UnsupportedVersion { found } => write!(
+                f,
+                "save has format version {}, but only versions 1 to {} are supported",
+                found, SAVE_FORMAT_VERSION
+            ),
+            Self::ChunkSizeMismatch { found } => write!(
+                f,
+                "save has chunks of {} tiles, but this game uses chunks of {} tiles",
+                found, CHUNK_SIZE
+            ),
+            Self::MissingModule { namespace, version } => write!(
+                f,
+                "save requires module '{}' {}, which is not loaded",
+                namespace, version
+            ),
+            Self::IncompatibleModule {
+                namespace,
+                saved,
+                loaded,
+            } => write!(
+                f,
+                "save requires module '{}' {}, which is incompatible with the loaded version {}",
+                namespace, saved, loaded
+            ),
+            Self::UnknownBuilding { id } => write!(f, "save contains unknown building '{}'", id),
+            Self::UnknownTerrain { id } => write!(f, "save contains unknown terrain '{}'", id),
+            Self::UnknownUnit { id } => write!(f, "save contains unknown unit '{}'", id),
+            Self::InvalidControlGroup { key, reason } => {
+                write!(f, "saved control group {} is invalid: {}", key, reason)
+            }
+            Self::InvalidChunk { position, reason } => {
+                write!(f, "saved chunk {} is invalid: {}", position, reason)
+            }
+            Self::InvalidRallyPoint { origin, reason } => write!(
+                f,
+                "rally point of saved building at {} is invalid: {}",
+                origin, reason
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+impl From<std::io::Error> for SaveError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<ron::Error> for SaveError {
+    fn from(error: ron::Error) -> Self {
+        Self::Serialize(error)
+    }
+}
+
+impl From<ron::error::SpannedError> for SaveError {
+    fn from(error: ron::error::SpannedError) -> Self {
+        Self::Parse(error)
+    }
+}
+
+/// Requests saving the game to the given file.
+#[derive(Message)]
+pub struct SaveGameRequest {
+    pub path: PathBuf,
+}
+
+/// Requests replacing the current game with the one saved in the given file.
+#[derive(Message)]
+pub struct LoadGameRequest {
+    pub path: PathBuf,
+}
+
+fn quicksave_path() -> PathBuf {
+    FileAssetReader::get_base_path()
+        .join(SAVES_FOLDER)
+        .join(QUICKSAVE_FILE_NAME)
+}
+
+/// F6 saves to and F9 loads from the quicksave file.
+fn quicksave_hotkeys(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut save_requests: MessageWriter<SaveGameRequest>,
+    mut load_requests: MessageWriter<LoadGameRequest>,
+) {
+    if keyboard_input.just_pressed(key_bindings::QUICKSAVE) {
+        save_requests.write(SaveGameRequest {
+            path: quicksave_path(),
+        });
+    }
+    if keyboard_input.just_pressed(key_bindings::QUICKLOAD) {
+        load_requests.write(LoadGameRequest {
+            path: quicksave_path(),
+        });
+    }
+}
+
+/// Everything a save is captured from and restored into.
+#[derive(SystemParam)]
+struct GameState<'w, 's> {
+    commands: Commands<'w, 's>,
+    map: ResMut<'w, Map>,
+    generator: ResMut<'w, WorldGenerator>,
+    terrain: Res<'w, TerrainRegistry>,
+    buildings: Res<'w, BuildingRegistry>,
+    modules: Res<'w, ModuleLoader>,
+    units: Res<'w, UnitRegistry>,
+    resources: ResMut<'w, PlayerResources>,
+    control_groups: ResMut<'w, ControlGroups>,
+    placed_buildings: Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static PlacedBuilding,
+            Option<&'static ProductionQueue>,
+            Option<&'static RallyPoint>,
+        ),
+    >,
+    placed_units: Query<'w, 's, (Entity, &'static Unit, &'static Transform, &'static Health)>,
+    camera: Single<'w, 's, &'static mut PlayerCamera>,
+}
+
+impl GameState<'_, '_> {
+    fn capture(&self) -> SaveGame {
+        let mut terrain_kinds = Vec::new();
+        let mut terrain_indices: HashMap<TerrainId, u16> = HashMap::new();
+        let mut chunks: Vec<SavedChunk> = self
+            .map
+            .all_chunk_terrain()
+            .map(|(position, chunk_terrain)| SavedChunk {
+                position: position.into(),
+                terrain: encode_runs(chunk_terrain.iter().flatten().map(|&terrain_id| {
+                    *terrain_indices.entry(terrain_id).or_insert_with(|| {
+                        terrain_kinds.push(self.terrain.name(terrain_id).to_string());
+                        (terrain_kinds.len() - 1) as u16
+                    })
+                })),
+            })
+            .collect();
+        // keep saves of the same world comparable
+        chunks.sort_by_key(|chunk| chunk.position);
+
+        let mut buildings: Vec<(Entity, SavedBuilding)> = self
+            .placed_buildings
+            .iter()
+            .map(|(entity, placed, production, _)| {
+                let building = SavedBuilding {
+                    id: placed.id.clone(),
+                    origin: placed.origin.into(),
+                    rotation: placed.rotation,
+                    footprint: self
+                        .map
+                        .footprint(entity)
+                        .unwrap_or_default()
+                        .iter()
+                        .map(|&tile| tile.into())
+                        .collect(),
+                    production: production.map(|queue| SavedProduction {
+                        queue: queue
+                            .items
+                            .iter()
+                            .map(|item| SavedQueuedUnit {
+                                id: item.id.clone(),
+                                cost: item.cost,
+                                build_time: item.build_time,
+                            })
+                            .collect(),
+                        progress: queue.progress,
+                    }),
+                    // filled in once the indices of all buildings and units are known
+                    rally: None,
+                };
+                (entity, building)
+            })
+            .collect();
+        buildings.sort_by_key(|(_, building)| building.origin);
+
+        let mut units: Vec<(Entity, SavedUnit)> = self
+            .placed_units
+            .iter()
+            .map(|(entity, unit, transform, health)| {
+                let unit = SavedUnit {
+                    id: unit.id.clone(),
+                    position: transform.translation.truncate().into(),
+                    health: health.current,
+                };
+                (entity, unit)
+            })
+            .collect();
+        units.sort_by(|(_, a), (_, b)| {
+            a.id.cmp(&b.id)
+                .then(a.position.0.total_cmp(&b.position.0))
+                .then(a.position.1.total_cmp(&b.position.1))
+        });
+
+        let members: HashMap<Entity, SavedGroupMember> = buildings
+            .iter()
+            .enumerate()
+            .map(|(index, (entity, _))| (*entity, SavedGroupMember::Building(index)))
+            .chain(
+                units
+                    .iter()
+                    .enumerate()
+                    .map(|(index, (entity, _))| (*entity, SavedGroupMember::Unit(index))),
+            )
+            .collect();
+        for (entity, building) in &mut buildings {
+            let Ok((.., Some(rally))) = self.placed_buildings.get(*entity) else {
+                continue;
+            };
+            building.rally = match *rally {
+                RallyPoint::Point(point) => Some(SavedRallyPoint::Point(point.into())),
+                RallyPoint::Entity(target) => members.get(&target).map(|member| match *member {
+                    SavedGroupMember::Building(index) => SavedRallyPoint::Building(index),
+                    SavedGroupMember::Unit(index) => SavedRallyPoint::Unit(index),
+                }),
+            };
+        }
+        let control_groups = (0..GROUP_COUNT)
+            .filter_map(|key| {
+                let members: Vec<SavedGroupMember> = self
+                    .control_groups
+                    .get(key)
+                    .iter()
+                    .filter_map(|entity| members.get(entity).copied())
+                    .collect();
+                (!members.is_empty()).then_some(SavedControlGroup {
+                    key: key as u8,
+                    members,
+                })
+            })
+            .collect();
+
+        let (position, scale) = self.camera.target_view();
+        SaveGame {
+            version: SAVE_FORMAT_VERSION,
+            modules: self
+                .modules
+                .applied_modules()
+                .map(|manifest| SavedModule {
+                    namespace: manifest.namespace.clone(),
+                    version: manifest.version.clone(),
+                })
+                .collect(),
+            seed: self.generator.seed(),
+            camera: SavedCamera {
+                position: position.into(),
+                scale,
+            },
+            chunk_size: CHUNK_SIZE as u16,
+            terrain_kinds,
+            chunks,
+            buildings: buildings
+                .into_iter()
+                .map(|(_, building)| building)
+                .collect(),
+            units: units.into_iter().map(|(_, unit)| unit).collect(),
+            credits: self.resources.credits,
+            control_groups,
+        }
+    }
+
+    /// Checks that everything the save refers to is known to this game.
+    fn validate(&self, save: &SaveGame) -> Result<Vec<(IVec2, ChunkTerrain)>, SaveError> {
+        if usize::from(save.chunk_size) != CHUNK_SIZE {
+            return Err(SaveError::ChunkSizeMismatch {
+                found: save.chunk_size,
+            });
+        }
+
+        for module in &save.modules {
+            let Some(loaded) = self
+                .modules
+                .applied_modules()
+                .find(|manifest| manifest.namespace == module.namespace)
+            else {
+                return Err(SaveError::MissingModule {
+                    namespace: module.namespace.clone(),
+                    version: module.version.clone(),
+                });
+            };
+            let compatible = VersionReq::parse(&format!("^{}", module.version))
+                .is_ok_and(|requirement| requirement.matches(&loaded.version));
+            if !compatible {
+                return Err(SaveError::IncompatibleModule {
+                    namespace: module.namespace.clone(),
+                    saved: module.version.clone(),
+                    loaded: loaded.version.clone(),
+                });
+            }
+        }
+
+        for building in &save.buildings {
+            if !self.buildings.buildings.contains_key(&building.id) {
+                return Err(SaveError::UnknownBuilding {
+                    id: building.id.clone(),
+                });
+            }
+            let exists = match building.rally {
+                Some(SavedRallyPoint::Building(index)) => index < save.buildings.len(),
+                Some(SavedRallyPoint::Unit(index)) => index < save.units.len(),
+                _ => true,
+            };
+            if !exists {
+                return Err(SaveError::InvalidRallyPoint {
+                    origin: building.origin.into(),
+                    reason: format!("target {:?} does not exist", building.rally),
+                });
+            }
+        }
+
+        let queued_units = save
+            .buildings
+            .iter()
+            .filter_map(|building| building.production.as_ref())
+            .flat_map(|production| production.queue.iter().map(|item| &item.id));
+        for id in save.units.iter().map(|unit| &unit.id).chain(queued_units) {
+            if !self.units.units.contains_key(id) {
+                return Err(SaveError::UnknownUnit { id: id.clone() });
+            }
+        }
+
+        for group in &save.control_groups {
+            if usize::from(group.key) >= GROUP_COUNT {
+                return Err(SaveError::InvalidControlGroup {
+                    key: group.key,
+                    reason: "key must be a digit from 0 to 9".to_string(),
+                });
+            }
+            for member in &group.members {
+                let exists = match *member {
+                    SavedGroupMember::Building(index) => index < save.buildings.len(),
+                    SavedGroupMember::Unit(index) => index < save.units.len(),
+                };
+                if !exists {
+                    return Err(SaveError::InvalidControlGroup {
+                        key: group.key,
+                        reason: format!("member {:?} does not exist", member),
+                    });
+                }
+            }
+        }
+
+        let terrain_ids = save
+            .terrain_kinds
+            .iter()
+            .map(|name| {
+                self.terrain
+                    .id(name)
+                    .ok_or_else(|| SaveError::UnknownTerrain { id: name.clone() })
+            })
+            .collect::<Result<Vec<TerrainId>, SaveError>>()?;
+        save.chunks
+            .iter()
+            .map(|chunk| Ok((chunk.position.into(), chunk.decode(&terrain_ids)?)))
+            .collect()
+    }
+
+    /// Replaces the current game with the save.
+    /// Returns problems with individual buildings, which are left out of the game.
+    fn restore(&mut self, save: &SaveGame) -> Result<Vec<String>, SaveError> {
+        let chunks = self.validate(save)?;
+
+        for (entity, ..) in &self.placed_buildings {
+            self.commands.entity(entity).despawn();
+        }
+        for (entity, ..) in &self.placed_units {
+            self.commands.entity(entity).despawn();
+        }
+        self.map.clear(&mut self.commands);
+        *self.generator = WorldGenerator::new(save.seed);
+        for (position, chunk_terrain) in chunks {
+            self.map.restore_chunk(position, chunk_terrain);
+        }
+
+        let mut problems = Vec::new();
+        // spawned entity of every saved building and unit, to restore the control groups
+        let mut building_entities = Vec::with_capacity(save.buildings.len());
+        let mut unit_entities = Vec::with_capacity(save.units.len());
+        for building in &save.buildings {
+            let entry = &self.buildings.buildings[&building.id];
+            let origin = IVec2::from(building.origin);
+            let entity = entry
+                .builder
+                .build(entry, &mut self.commands, origin, building.rotation);
+            let footprint: Vec<IVec2> =
+                building.footprint.iter().map(|&tile| tile.into()).collect();
+            if let Err(error) = self.map.restore_footprint(entity, &footprint) {
+                problems.push(format!(
+                    "building '{}' at {}: {}",
+                    building.id, origin, error
+                ));
+                self.commands.entity(entity).despawn();
+                building_entities.push(None);
+                continue;
+            }
+            building_entities.push(Some(entity));
+            self.commands.entity(entity).insert(PlacedBuilding {
+                id: building.id.clone(),
+                origin,
+                rotation: building.rotation,
+            });
+            if let Some(production) = &building.production {
+                self.commands.entity(entity).insert(ProductionQueue {
+                    items: production
+                        .queue
+                        .iter()
+                        .map(|item| QueuedUnit {
+                            id: item.id.clone(),
+                            cost: item.cost,
+                            build_time: item.build_time,
+                        })
+                        .collect(),
+                    progress: production.progress,
+                });
+            }
+        }
+
+        for unit in &save.units {
+            let entity = self
+                .units
+                .spawn(&unit.id, unit.position.into(), &mut self.commands);
+            unit_entities.push(entity);
+            let Some(entity) = entity else {
+                continue;
+            };
+            let max = self.units.units[&unit.id].health;
+            self.commands.entity(entity).insert(Health {
+                current: unit.health.min(max),
+                max,
+            });
+        }
+
+        for (building, entity) in save.buildings.iter().zip(&building_entities) {
+            let (Some(rally), Some(entity)) = (building.rally, *entity) else {
+                continue;
+            };
+            let rally = match rally {
+                SavedRallyPoint::Point(point) => Some(RallyPoint::Point(point.into())),
+                SavedRallyPoint::Building(index) => {
+                    building_entities[index].map(RallyPoint::Entity)
+                }
+                SavedRallyPoint::Unit(index) => unit_entities[index].map(RallyPoint::Entity),
+            };
+            if let Some(rally) = rally {
+                self.commands.entity(entity).insert(rally);
+            }
+        }
+
+        self.control_groups.clear();
+        for group in &save.control_groups {
+            let entities = group
+                .members
+                .iter()
+                .filter_map(|member| match *member {
+                    SavedGroupMember::Building(index) => building_entities[index],
+                    SavedGroupMember::Unit(index) => unit_entities[index],
+                })
+                .collect();
+            self.control_groups.set(group.key.into(), entities);
+        }
+
+        self.resources.credits = save.credits;
+        self.camera
+            .set_target_view(save.camera.position.into(), save.camera.scale);
+        Ok(problems)
+    }
+}
+
+fn write_save(path: &PathBuf, save: &SaveGame) -> Result<(), SaveError> {
+    let content =
+        ron::ser::to_string_pretty(save, ron::ser::PrettyConfig::new().compact_arrays(true))?;
+    if let Some(folder) = path.parent() {
+        std::fs::create_dir_all(folder)?;
+    }
+    std::fs::write(path, content)?;
+    Ok(())
+}
+
+fn read_save(path: &PathBuf) -> Result<SaveGame, SaveError> {
+    let content = std::fs::read_to_string(path)?;
+    let header: SaveHeader = ron::de::from_str(&content)?;
+    upgrade_save(header.version, &content)
+}
+
+/// Parses a save written in the given format version and migrates it step by step
+/// to [`SAVE_FORMAT_VERSION`].
+///
+/// When the format changes, the current types move into a module of their own
+/// with a `migrate` method producing the new version, and get a branch here.
+fn upgrade_save(version: u32, content: &str) -> Result<SaveGame, SaveError> {
+    Ok(match version {
+        1 => ron::de::from_str::<v1::SaveGame>(content)?
+            .migrate()
+            .migrate()
+            .migrate()
+            .migrate()
+            .migrate(),
+        2 => ron::de::from_str::<v2::SaveGame>(content)?
+            .migrate()
+            .migrate()
+            .migrate()
+            .migrate(),
+        3 => ron::de::from_str::<v3::SaveGame>(content)?
+            .migrate()
+            .migrate()
+            .migrate(),
+        4 => ron::de::from_str::<v4::SaveGame>(content)?
+            .migrate()
+            .migrate(),
+        5 => ron::de::from_str::<v5::SaveGame>(content)?.migrate(),
+        SAVE_FORMAT_VERSION => ron::de::from_str(content)?,
+        found => return Err(SaveError::UnsupportedVersion { found }),
+    })
+}
+
+fn save_game(
+    mut requests: MessageReader<SaveGameRequest>,
+    state: GameState,
+    mut toasts: MessageWriter<ToastMessage>,
+) {
+    for request in requests.read() {
+        let save = state.capture();
+        let content = match write_save(&request.path, &save) {
+            Ok(()) => format!(
+                "Saved game with {} buildings and {} units to '{}'",
+                save.buildings.len(),
+                save.units.len(),
+                request.path.display()
+            ),
+            Err(error) => {
+                error!("Saving to '{}' failed: {}", request.path.display(), error);
+                format!("Saving failed: {}", error)
+            }
+        };
+        toasts.write(ToastMessage { content });
+    }
+}
+
+fn load_game(
+    mut requests: MessageReader<LoadGameRequest>,
+    mut state: GameState,
+    mut toasts: MessageWriter<ToastMessage>,
+) {
+    for request in requests.read() {
+        let result = read_save(&request.path).and_then(|save| state.restore(&save));
+        let content = match result {
+            Ok(problems) => {
+                for problem in &problems {
+                    warn!("Loading '{}': {}", request.path.display(), problem);
+                }
+                if problems.is_empty() {
+                    format!("Loaded game from '{}'", request.path.display())
+                } else {
+                    format!(
+                        "Loaded game from '{}', {} buildings could not be restored",
+                        request.path.display(),
+                        problems.len()
+                    )
+                }
+            }
+            Err(error) => {
+                error!("Loading '{}' failed: {}", request.path.display(), error);
+                format!("Loading failed: {}", error)
+            }
+        };
+        toasts.write(ToastMessage { content });
+    }
+}
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_message::<SaveGameRequest>()
+            .add_message::<LoadGameRequest>()
+            .add_systems(
+                Update,
+                (quicksave_hotkeys, save_game, load_game)
+                    .chain()
+                    .run_if(in_state(AppState::Game)),
+            );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::terrain::TerrainDefinitions;
+
+    fn core_terrain() -> TerrainRegistry {
+        let path =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/modules/core/core.terrain.ron");
+        let definitions: TerrainDefinitions =
+            ron::de::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        let mut registry = TerrainRegistry::default();
+        for kind in definitions.0 {
+            registry.register(kind).unwrap();
+        }
+        registry
+    }
+
+    /// Content of the newest sample save, reduced to what the format `version` can hold.
+    /// Migrating the sample of `version` must result in exactly this.
+    fn expected_content(mut save: SaveGame, version: u32) -> SaveGame {
+        if version < 6 {
+            for building in &mut save.buildings {
+                building.rally = None;
+            }
+        }
+        if version < 5 {
+            save.control_groups.clear();
+        }
+        if version < 4 {
+            for building in &mut save.buildings {
+                building.production = None;
+            }
+            save.credits = 500;
+        }
+        if version < 3 {
+            save.units.clear();
+        }
+        save
+    }
+
+    /// Every format version has a sample save, and all of them must still load.
+    #[test]
+    fn sample_saves_load() {
+        let samples = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/saves/samples");
+        let terrain = core_terrain();
+        let newest = read_save(&samples.join(format!("v{}.ron", SAVE_FORMAT_VERSION))).unwrap();
+        let mut loaded = Vec::new();
+        for version in 1..=SAVE_FORMAT_VERSION {
+            let path = samples.join(format!("v{}.ron", version));
+            let save = read_save(&path)
+                .unwrap_or_else(|error| panic!("{} failed to load: {}", path.display(), error));
+            assert_eq!(save.version, SAVE_FORMAT_VERSION);
+            assert_eq!(usize::from(save.chunk_size), CHUNK_SIZE);
+
+            let expected = expected_content(newest.clone(), version);
+            let name = path.display();
+            assert_eq!(save.buildings, expected.buildings, "buildings of {}", name);
+            assert_eq!(save.units, expected.units, "units of {}", name);
+            assert_eq!(save.credits, expected.credits, "credits of {}", name);
+            assert_eq!(
+                save.control_groups, expected.control_groups,
+                "control groups of {}",
+                name
+            );
+
+            let terrain_ids: Vec<TerrainId> = save
+                .terrain_kinds
+                .iter()
+                .map(|name| terrain.id(name).expect("sample uses core terrain"))
+                .collect();
+            let chunks = save
+                .chunks
+                .iter()
+                .map(|chunk| Ok((chunk.position, chunk.decode(&terrain_ids)?)))
+                .collect::<Result<Vec<_>, SaveError>>()
+                .unwrap_or_else(|error| {
+                    panic!("{} has an invalid chunk: {}", path.display(), error)
+                });
+            loaded.push(chunks);
+        }
+        // the samples all describe the same world, so migrations must not change it
+        assert!(loaded.windows(2).all(|pair| pair[0] == pair[1]));
+    }
+}
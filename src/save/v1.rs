@@ -0,0 +1,106 @@
+//! Save format version 1, kept to read and upgrade old saves.
+//! These types must not change anymore.
+
+use semver::Version;
+use serde::Deserialize;
+
+/// Chunk size all version 1 saves were written with.
+const CHUNK_SIZE: u16 = 16;
+
+#[derive(Deserialize)]
+pub struct SavedModule {
+    pub namespace: String,
+    pub version: Version,
+}
+
+#[derive(Deserialize)]
+pub struct SavedCamera {
+    pub position: (f32, f32),
+    pub scale: f32,
+}
+
+#[derive(Deserialize)]
+pub struct SavedChunk {
+    pub position: (i32, i32),
+    /// Terrain index of every tile, ordered by local x first and local y second.
+    pub terrain: Vec<u16>,
+}
+
+#[derive(Deserialize)]
+pub enum BuildingRotation {
+    Deg0,
+    Deg90,
+    Deg180,
+    Deg270,
+}
+
+impl BuildingRotation {
+    fn migrate(self) -> super::v2::BuildingRotation {
+        match self {
+            Self::Deg0 => super::v2::BuildingRotation::Deg0,
+            Self::Deg90 => super::v2::BuildingRotation::Deg90,
+            Self::Deg180 => super::v2::BuildingRotation::Deg180,
+            Self::Deg270 => super::v2::BuildingRotation::Deg270,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SavedBuilding {
+    pub id: String,
+    pub origin: (i32, i32),
+    pub rotation: BuildingRotation,
+    pub footprint: Vec<(i32, i32)>,
+}
+
+#[derive(Deserialize)]
+pub struct SaveGame {
+    pub modules: Vec<SavedModule>,
+    pub seed: u64,
+    pub camera: SavedCamera,
+    pub terrain_kinds: Vec<String>,
+    pub chunks: Vec<SavedChunk>,
+    pub buildings: Vec<SavedBuilding>,
+}
+
+impl SaveGame {
+    /// Upgrades the save to version 2, which stores the chunk size and run-length encodes
+    /// the terrain of chunks.
+    pub fn migrate(self) -> super::v2::SaveGame {
+        super::v2::SaveGame {
+            modules: self
+                .modules
+                .into_iter()
+                .map(|module| super::v2::SavedModule {
+                    namespace: module.namespace,
+                    version: module.version,
+                })
+                .collect(),
+            seed: self.seed,
+            camera: super::v2::SavedCamera {
+                position: self.camera.position,
+                scale: self.camera.scale,
+            },
+            chunk_size: CHUNK_SIZE,
+            terrain_kinds: self.terrain_kinds,
+            chunks: self
+                .chunks
+                .into_iter()
+                .map(|chunk| super::v2::SavedChunk {
+                    position: chunk.position,
+                    terrain: super::encode_runs(chunk.terrain),
+                })
+                .collect(),
+            buildings: self
+                .buildings
+                .into_iter()
+                .map(|building| super::v2::SavedBuilding {
+                    id: building.id,
+                    origin: building.origin,
+                    rotation: building.rotation.migrate(),
+                    footprint: building.footprint,
+                })
+                .collect(),
+        }
+    }
+}
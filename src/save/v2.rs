@@ -0,0 +1,104 @@
+//! Save format version 2, kept to read and upgrade old saves.
+//! These types must not change anymore.
+
+use semver::Version;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct SavedModule {
+    pub namespace: String,
+    pub version: Version,
+}
+
+#[derive(Deserialize)]
+pub struct SavedCamera {
+    pub position: (f32, f32),
+    pub scale: f32,
+}
+
+#[derive(Deserialize)]
+pub struct SavedChunk {
+    pub position: (i32, i32),
+    /// Runs of `(terrain index, tile count)`, ordered by local x first and local y second.
+    pub terrain: Vec<(u16, u16)>,
+}
+
+#[derive(Deserialize)]
+pub enum BuildingRotation {
+    Deg0,
+    Deg90,
+    Deg180,
+    Deg270,
+}
+
+impl BuildingRotation {
+    fn migrate(self) -> super::v3::BuildingRotation {
+        match self {
+            Self::Deg0 => super::v3::BuildingRotation::Deg0,
+            Self::Deg90 => super::v3::BuildingRotation::Deg90,
+            Self::Deg180 => super::v3::BuildingRotation::Deg180,
+            Self::Deg270 => super::v3::BuildingRotation::Deg270,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SavedBuilding {
+    pub id: String,
+    pub origin: (i32, i32),
+    pub rotation: BuildingRotation,
+    pub footprint: Vec<(i32, i32)>,
+}
+
+#[derive(Deserialize)]
+pub struct SaveGame {
+    pub modules: Vec<SavedModule>,
+    pub seed: u64,
+    pub camera: SavedCamera,
+    pub chunk_size: u16,
+    pub terrain_kinds: Vec<String>,
+    pub chunks: Vec<SavedChunk>,
+    pub buildings: Vec<SavedBuilding>,
+}
+
+impl SaveGame {
+    /// Upgrades the save to version 3, which adds units.
+    pub fn migrate(self) -> super::v3::SaveGame {
+        super::v3::SaveGame {
+            modules: self
+                .modules
+                .into_iter()
+                .map(|module| super::v3::SavedModule {
+                    namespace: module.namespace,
+                    version: module.version,
+                })
+                .collect(),
+            seed: self.seed,
+            camera: super::v3::SavedCamera {
+                position: self.camera.position,
+                scale: self.camera.scale,
+            },
+            chunk_size: self.chunk_size,
+            terrain_kinds: self.terrain_kinds,
+            chunks: self
+                .chunks
+                .into_iter()
+                .map(|chunk| super::v3::SavedChunk {
+                    position: chunk.position,
+                    terrain: chunk.terrain,
+                })
+                .collect(),
+            buildings: self
+                .buildings
+                .into_iter()
+                .map(|building| super::v3::SavedBuilding {
+                    id: building.id,
+                    origin: building.origin,
+                    rotation: building.rotation.migrate(),
+                    footprint: building.footprint,
+                })
+                .collect(),
+            units: Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,123 @@
+//! Save format version 3, kept to read and upgrade old saves.
+//! These types must not change anymore.
+
+use semver::Version;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct SavedModule {
+    pub namespace: String,
+    pub version: Version,
+}
+
+#[derive(Deserialize)]
+pub struct SavedCamera {
+    pub position: (f32, f32),
+    pub scale: f32,
+}
+
+#[derive(Deserialize)]
+pub struct SavedChunk {
+    pub position: (i32, i32),
+    /// Runs of `(terrain index, tile count)`, ordered by local x first and local y second.
+    pub terrain: Vec<(u16, u16)>,
+}
+
+#[derive(Deserialize)]
+pub enum BuildingRotation {
+    Deg0,
+    Deg90,
+    Deg180,
+    Deg270,
+}
+
+impl BuildingRotation {
+    fn migrate(self) -> super::v4::BuildingRotation {
+        match self {
+            Self::Deg0 => super::v4::BuildingRotation::Deg0,
+            Self::Deg90 => super::v4::BuildingRotation::Deg90,
+            Self::Deg180 => super::v4::BuildingRotation::Deg180,
+            Self::Deg270 => super::v4::BuildingRotation::Deg270,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SavedBuilding {
+    pub id: String,
+    pub origin: (i32, i32),
+    pub rotation: BuildingRotation,
+    pub footprint: Vec<(i32, i32)>,
+}
+
+#[derive(Deserialize)]
+pub struct SavedUnit {
+    pub id: String,
+    pub position: (f32, f32),
+    pub health: u32,
+}
+
+#[derive(Deserialize)]
+pub struct SaveGame {
+    pub modules: Vec<SavedModule>,
+    pub seed: u64,
+    pub camera: SavedCamera,
+    pub chunk_size: u16,
+    pub terrain_kinds: Vec<String>,
+    pub chunks: Vec<SavedChunk>,
+    pub buildings: Vec<SavedBuilding>,
+    pub units: Vec<SavedUnit>,
+}
+
+impl SaveGame {
+    /// Upgrades the save to version 4, which adds credits and production queues.
+    /// Games saved before start with 500 credits and empty queues.
+    pub fn migrate(self) -> super::v4::SaveGame {
+        super::v4::SaveGame {
+            modules: self
+                .modules
+                .into_iter()
+                .map(|module| super::v4::SavedModule {
+                    namespace: module.namespace,
+                    version: module.version,
+                })
+                .collect(),
+            seed: self.seed,
+            camera: super::v4::SavedCamera {
+                position: self.camera.position,
+                scale: self.camera.scale,
+            },
+            chunk_size: self.chunk_size,
+            terrain_kinds: self.terrain_kinds,
+            chunks: self
+                .chunks
+                .into_iter()
+                .map(|chunk| super::v4::SavedChunk {
+                    position: chunk.position,
+                    terrain: chunk.terrain,
+                })
+                .collect(),
+            buildings: self
+                .buildings
+                .into_iter()
+                .map(|building| super::v4::SavedBuilding {
+                    id: building.id,
+                    origin: building.origin,
+                    rotation: building.rotation.migrate(),
+                    footprint: building.footprint,
+                    production: None,
+                })
+                .collect(),
+            units: self
+                .units
+                .into_iter()
+                .map(|unit| super::v4::SavedUnit {
+                    id: unit.id,
+                    position: unit.position,
+                    health: unit.health,
+                })
+                .collect(),
+            credits: 500,
+        }
+    }
+}
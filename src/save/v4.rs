@@ -0,0 +1,151 @@
+//! Save format version 4, kept to read and upgrade old saves.
+//! These types must not change anymore.
+
+use semver::Version;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct SavedModule {
+    pub namespace: String,
+    pub version: Version,
+}
+
+#[derive(Deserialize)]
+pub struct SavedCamera {
+    pub position: (f32, f32),
+    pub scale: f32,
+}
+
+#[derive(Deserialize)]
+pub struct SavedChunk {
+    pub position: (i32, i32),
+    /// Runs of `(terrain index, tile count)`, ordered by local x first and local y second.
+    pub terrain: Vec<(u16, u16)>,
+}
+
+#[derive(Deserialize)]
+pub enum BuildingRotation {
+    Deg0,
+    Deg90,
+    Deg180,
+    Deg270,
+}
+
+impl BuildingRotation {
+    fn migrate(self) -> super::v5::BuildingRotation {
+        match self {
+            Self::Deg0 => super::v5::BuildingRotation::Deg0,
+            Self::Deg90 => super::v5::BuildingRotation::Deg90,
+            Self::Deg180 => super::v5::BuildingRotation::Deg180,
+            Self::Deg270 => super::v5::BuildingRotation::Deg270,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SavedBuilding {
+    pub id: String,
+    pub origin: (i32, i32),
+    pub rotation: BuildingRotation,
+    pub footprint: Vec<(i32, i32)>,
+    pub production: Option<SavedProduction>,
+}
+
+#[derive(Deserialize)]
+pub struct SavedQueuedUnit {
+    pub id: String,
+    pub cost: u32,
+    pub build_time: f32,
+}
+
+#[derive(Deserialize)]
+pub struct SavedProduction {
+    pub queue: Vec<SavedQueuedUnit>,
+    pub progress: f32,
+}
+
+#[derive(Deserialize)]
+pub struct SavedUnit {
+    pub id: String,
+    pub position: (f32, f32),
+    pub health: u32,
+}
+
+#[derive(Deserialize)]
+pub struct SaveGame {
+    pub modules: Vec<SavedModule>,
+    pub seed: u64,
+    pub camera: SavedCamera,
+    pub chunk_size: u16,
+    pub terrain_kinds: Vec<String>,
+    pub chunks: Vec<SavedChunk>,
+    pub buildings: Vec<SavedBuilding>,
+    pub units: Vec<SavedUnit>,
+    pub credits: u32,
+}
+
+impl SaveGame {
+    /// Upgrades the save to version 5, which adds control groups.
+    pub fn migrate(self) -> super::v5::SaveGame {
+        super::v5::SaveGame {
+            modules: self
+                .modules
+                .into_iter()
+                .map(|module| super::v5::SavedModule {
+                    namespace: module.namespace,
+                    version: module.version,
+                })
+                .collect(),
+            seed: self.seed,
+            camera: super::v5::SavedCamera {
+                position: self.camera.position,
+                scale: self.camera.scale,
+            },
+            chunk_size: self.chunk_size,
+            terrain_kinds: self.terrain_kinds,
+            chunks: self
+                .chunks
+                .into_iter()
+                .map(|chunk| super::v5::SavedChunk {
+                    position: chunk.position,
+                    terrain: chunk.terrain,
+                })
+                .collect(),
+            buildings: self
+                .buildings
+                .into_iter()
+                .map(|building| super::v5::SavedBuilding {
+                    id: building.id,
+                    origin: building.origin,
+                    rotation: building.rotation.migrate(),
+                    footprint: building.footprint,
+                    production: building
+                        .production
+                        .map(|production| super::v5::SavedProduction {
+                            queue: production
+                                .queue
+                                .into_iter()
+                                .map(|item| super::v5::SavedQueuedUnit {
+                                    id: item.id,
+                                    cost: item.cost,
+                                    build_time: item.build_time,
+                                })
+                                .collect(),
+                            progress: production.progress,
+                        }),
+                })
+                .collect(),
+            units: self
+                .units
+                .into_iter()
+                .map(|unit| super::v5::SavedUnit {
+                    id: unit.id,
+                    position: unit.position,
+                    health: unit.health,
+                })
+                .collect(),
+            credits: self.credits,
+            control_groups: Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,280 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+use crate::{
+    AppState, InputMode, MouseCursor, PlacedBuilding,
+    chunk_streaming::visible_world_rect,
+    map::{FIELD_SIZE, Map},
+    player_camera::PlayerCamera,
+    units::{Unit, UnitRegistry},
+    user_controls::{EntityPicker, control_panel_hovered},
+};
+
+/// Seconds between two clicks on the same entity that make a double click.
+const DOUBLE_CLICK_TIME: f32 = 0.3;
+/// Distance in world units the cursor has to move while the left mouse button is held
+/// to drag a selection box instead of clicking.
+const DRAG_THRESHOLD: f32 = FIELD_SIZE / 2.0;
+
+/// Entities selected by the player, in the order they were selected.
+#[derive(Resource, Debug, Default)]
+pub struct Selection {
+    entities: Vec<Entity>,
+}
+
+impl Selection {
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
+    /// Replaces the selection.
+    pub fn set(&mut self, entities: impl IntoIterator<Item = Entity>) {
+        self.entities.clear();
+        self.add(entities);
+    }
+
+    /// Adds the entities that are not selected yet.
+    pub fn add(&mut self, entities: impl IntoIterator<Item = Entity>) {
+        for entity in entities {
+            if !self.entities.contains(&entity) {
+                self.entities.push(entity);
+            }
+        }
+    }
+
+    /// Selects the entity if it is not selected, deselects it otherwise.
+    pub fn toggle(&mut self, entity: Entity) {
+        match self
+            .entities
+            .iter()
+            .position(|&selected| selected == entity)
+        {
+            Some(index) => {
+                self.entities.remove(index);
+            }
+            None => self.entities.push(entity),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entities.clear();
+    }
+}
+
+type SelectableData<'a> = (Entity, &'a Transform, AnyOf<(&'a Unit, &'a PlacedBuilding)>);
+
+/// Units and placed buildings, everything the player can select.
+#[derive(SystemParam)]
+pub struct Selectables<'w, 's> {
+    entities: Query<'w, 's, SelectableData<'static>>,
+}
+
+impl Selectables<'_, '_> {
+    /// Registry id of a selectable entity, which tells its type, e.g. `core:worker`.
+    pub fn type_of(&self, entity: Entity) -> Option<&str> {
+        let (_, _, (unit, building)) = self.entities.get(entity).ok()?;
+        Some(match (unit, building) {
+            (Some(unit), _) => &unit.id,
+            (None, Some(building)) => &building.id,
+            (None, None) => unreachable!("AnyOf matches at least one component"),
+        })
+    }
+
+    /// Selectable entities of the given type whose position lies inside `area`.
+    fn of_type_in(&self, entity_type: &str, area: Rect) -> Vec<Entity> {
+        self.entities
+            .iter()
+            .filter(|&(entity, transform, _)| {
+                self.type_of(entity) == Some(entity_type)
+                    && area.contains(transform.translation.truncate())
+            })
+            .map(|(entity, ..)| entity)
+            .collect()
+    }
+
+    /// Units whose position lies inside `area`.
+    fn units_in(&self, area: Rect) -> Vec<Entity> {
+        self.entities
+            .iter()
+            .filter(|(_, transform, (unit, _))| {
+                unit.is_some() && area.contains(transform.translation.truncate())
+            })
+            .map(|(entity, ..)| entity)
+            .collect()
+    }
+}
+
+/// State of the left mouse button used for selecting in [`InputMode::Normal`].
+#[derive(Resource, Default)]
+struct SelectionInput {
+    /// World position the button was pressed at, while it is held.
+    drag_start: Option<Vec2>,
+    /// Entity clicked last and the time of the click, to detect double clicks.
+    last_click: Option<(Entity, f32)>,
+}
+
+fn start_selection(
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    cursor: Res<MouseCursor>,
+    mut input: ResMut<SelectionInput>,
+) {
+    if mouse_input.just_pressed(MouseButton::Left) {
+        input.drag_start = cursor.world_position();
+    }
+}
+
+fn cancel_selection(mut input: ResMut<SelectionInput>) {
+    input.drag_start = None;
+}
+
+/// Selects on release of the left mouse button:
+/// - a click selects the entity under the cursor,
+/// - a double click selects every entity of its type on screen,
+/// - dragging a box selects the units inside.
+///
+/// With shift held, clicks add or remove the entity and boxes add to the selection.
+#[allow(clippy::too_many_arguments)]
+fn finish_selection(
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    time: Res<Time>,
+    cursor: Res<MouseCursor>,
+    picker: EntityPicker,
+    selectables: Selectables,
+    camera_query: Single<(&Camera, &GlobalTransform), With<PlayerCamera>>,
+    mut input: ResMut<SelectionInput>,
+    mut selection: ResMut<Selection>,
+) {
+    if !mouse_input.just_released(MouseButton::Left) {
+        return;
+    }
+    let (Some(start), Some(end)) = (input.drag_start.take(), cursor.world_position()) else {
+        return;
+    };
+    let shift = keyboard_input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+
+    if start.distance(end) >= DRAG_THRESHOLD {
+        let boxed = selectables.units_in(Rect::from_corners(start, end));
+        if shift {
+            selection.add(boxed);
+        } else {
+            selection.set(boxed);
+        }
+        return;
+    }
+
+    let Some(entity) = picker.entity_at(end) else {
+        input.last_click = None;
+        if !shift {
+            selection.clear();
+        }
+        return;
+    };
+    let now = time.elapsed_secs();
+    let double_click = input
+        .last_click
+        .is_some_and(|(last, at)| last == entity && now - at <= DOUBLE_CLICK_TIME);
+    input.last_click = Some((entity, now));
+
+    if double_click {
+        let (camera, camera_transform) = camera_query.into_inner();
+        let (Some(entity_type), Some(visible)) = (
+            selectables.type_of(entity),
+            visible_world_rect(camera, camera_transform),
+        ) else {
+            return;
+        };
+        let same_type = selectables.of_type_in(entity_type, visible);
+        if shift {
+            selection.add(same_type);
+        } else {
+            selection.set(same_type);
+        }
+    } else if shift {
+        selection.toggle(entity);
+    } else {
+        selection.set([entity]);
+    }
+}
+
+/// Deselects despawned entities.
+fn prune_selection(mut selection: ResMut<Selection>, entities: Query<()>) {
+    if selection
+        .entities
+        .iter()
+        .any(|&entity| !entities.contains(entity))
+    {
+        selection
+            .entities
+            .retain(|&entity| entities.contains(entity));
+    }
+}
+
+/// Draws a circle around selected units and outlines the footprint of selected buildings.
+fn draw_selection(
+    mut gizmos: Gizmos,
+    selection: Res<Selection>,
+    map: Res<Map>,
+    registry: Res<UnitRegistry>,
+    units: Query<(&Unit, &Transform)>,
+) {
+    let color = Color::srgb(0.3, 1.0, 0.3);
+    for &entity in selection.entities() {
+        if let Ok((unit, transform)) = units.get(entity) {
+            let radius = registry
+                .units
+                .get(&unit.id)
+                .map_or(0.5, |entry| entry.radius);
+            gizmos.circle_2d(
+                Isometry2d::from_translation(transform.translation.truncate()),
+                radius * FIELD_SIZE + 1.0,
+                color,
+            );
+        } else if let Some(footprint) = map.footprint(entity) {
+            for &tile in footprint {
+                gizmos.rect_2d(
+                    Isometry2d::from_translation(Map::tile_center(tile)),
+                    Vec2::splat(FIELD_SIZE),
+                    color,
+                );
+            }
+        }
+    }
+}
+
+fn draw_selection_box(mut gizmos: Gizmos, input: Res<SelectionInput>, cursor: Res<MouseCursor>) {
+    let (Some(start), Some(end)) = (input.drag_start, cursor.world_position()) else {
+        return;
+    };
+    if start.distance(end) < DRAG_THRESHOLD {
+        return;
+    }
+    let area = Rect::from_corners(start, end);
+    gizmos.rect_2d(
+        Isometry2d::from_translation(area.center()),
+        area.size(),
+        Color::srgba(0.3, 1.0, 0.3, 0.8),
+    );
+}
+
+pub struct SelectionPlugin;
+
+impl Plugin for SelectionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Selection>()
+            .init_resource::<SelectionInput>()
+            .add_systems(OnExit(InputMode::Normal), cancel_selection)
+            .add_systems(
+                Update,
+                (
+                    prune_selection,
+                    start_selection
+                        .run_if(in_state(InputMode::Normal).and(not(control_panel_hovered))),
+                    finish_selection.run_if(in_state(InputMode::Normal)),
+                    draw_selection,
+                    draw_selection_box.run_if(in_state(InputMode::Normal)),
+                )
+                    .chain()
+                    .run_if(in_state(AppState::Game)),
+            );
+    }
+}
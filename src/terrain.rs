@@ -0,0 +1,427 @@
+use std::collections::{HashMap, hash_map::Entry};
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    map::{CHUNK_SIZE, Map},
+    module_loader::RonAssetLoader,
+};
+
+/// Seed used for new worlds.
+pub const DEFAULT_WORLD_SEED: u64 = 0x5eed_ba77_1e00;
+
+fn default_movement_cost() -> f32 {
+    1.0
+}
+
+/// Kind of ground a tile can consist of, as written in a `*.terrain.ron` asset file of a
+/// content module.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TerrainKind {
+    /// Namespaced registry id, e.g. `core:grass`.
+    pub id: String,
+    /// Color as `(red, green, blue)` in sRGB.
+    pub color: (f32, f32, f32),
+    /// Whether buildings may be placed on this terrain.
+    pub buildable: bool,
+    /// Whether units may move across this terrain.
+    pub walkable: bool,
+    /// Cost of moving across one tile relative to open ground.
+    #[serde(default = "default_movement_cost")]
+    pub movement_cost: f32,
+    #[serde(default)]
+    pub blocks_vision: bool,
+}
+
+impl TerrainKind {
+    pub fn color(&self) -> Color {
+        let (red, green, blue) = self.color;
+        Color::srgb(red, green, blue)
+    }
+
+    /// Checks the terrain kind for problems that parsing alone cannot catch.
+    /// Returns a list of human readable problems if the terrain kind is invalid.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+        match self.id.split_once(':') {
+            Some((namespace, name)) if !namespace.is_empty() && !name.is_empty() => {}
+            _ => problems.push(format!(
+                "id '{}' must have the form 'namespace:name'",
+                self.id
+            )),
+        }
+        if self.movement_cost <= 0.0 {
+            problems.push(format!(
+                "movement cost must be positive, got {}",
+                self.movement_cost
+            ));
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+}
+
+/// Terrain kinds as written in a `*.terrain.ron` asset file of a content module.
+#[derive(Asset, TypePath, Debug, Clone, Deserialize)]
+#[serde(transparent)]
+pub struct TerrainDefinitions(pub Vec<TerrainKind>);
+
+/// Compact handle of a terrain kind in the [`TerrainRegistry`], stored for every tile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct TerrainId(u16);
+
+/// All known terrain kinds.
+///
+/// Kinds keep their [`TerrainId`] when they are replaced,
+/// so tiles of already generated chunks pick up the new properties.
+#[derive(Resource, Default)]
+pub struct TerrainRegistry {
+    kinds: Vec<TerrainKind>,
+    ids: HashMap<String, TerrainId>,
+}
+
+impl TerrainRegistry {
+    /// Registers a new terrain kind.
+    /// Fails if a terrain kind with the same id is already registered;
+    /// use [`TerrainRegistry::replace`] to override it explicitly.
+    pub fn register(&mut self, kind: TerrainKind) -> Result<TerrainId, String> {
+        let index = u16::try_from(self.kinds.len())
+            .map_err(|_| format!("too many terrain kinds to register '{}'", kind.id))?;
+        match self.ids.entry(kind.id.clone()) {
+            Entry::Vacant(e) => {
+                info!("Registering terrain: {} -> {:?}", e.key(), kind);
+                let id = TerrainId(index);
+                e.insert(id);
+                self.kinds.push(kind);
+                Ok(id)
+            }
+            Entry::Occupied(e) => Err(format!("terrain '{}' is already registered", e.key())),
+        }
+    }
+
+    /// Replaces an already registered terrain kind and returns the previous one.
+    /// Returns `None` and registers nothing if no terrain kind with that id exists.
+    pub fn replace(&mut self, kind: TerrainKind) -> Option<TerrainKind> {
+        let id = *self.ids.get(&kind.id)?;
+        info!("Replacing terrain: {} -> {:?}", kind.id, kind);
+        Some(std::mem::replace(&mut self.kinds[id.0 as usize], kind))
+    }
+
+    /// Returns the id of the terrain kind registered as `name`, e.g. `core:grass`.
+    pub fn id(&self, name: &str) -> Option<TerrainId> {
+        self.ids.get(name).copied()
+    }
+
+    /// Returns the registered name of a terrain kind, e.g. `core:grass`.
+    pub fn name(&self, id: TerrainId) -> &str {
+        &self.get(id).id
+    }
+
+    /// Lowest movement cost of all walkable terrain kinds, or `1.0` if there is none.
+    pub fn min_movement_cost(&self) -> f32 {
+        self.kinds
+            .iter()
+            .filter(|kind| kind.walkable)
+            .map(|kind| kind.movement_cost)
+            .reduce(f32::min)
+            .unwrap_or(1.0)
+    }
+
+    #[inline]
+    pub fn get(&self, id: TerrainId) -> &TerrainKind {
+        &self.kinds[id.0 as usize]
+    }
+}
+
+/// Broad kind of landscape the world generator decides on for every tile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Biome {
+    Grass,
+    Water,
+    Rock,
+    Forest,
+    Cliffs,
+}
+
+impl Biome {
+    const ALL: [Self; 5] = [
+        Self::Grass,
+        Self::Water,
+        Self::Rock,
+        Self::Forest,
+        Self::Cliffs,
+    ];
+
+    /// Id of the terrain kind the biome is generated as.
+    fn terrain(self) -> &'static str {
+        match self {
+            Self::Grass => "core:grass",
+            Self::Water => "core:water",
+            Self::Rock => "core:rock",
+            Self::Forest => "core:forest",
+            Self::Cliffs => "core:cliffs",
+        }
+    }
+}
+
+/// Terrain kinds the world generator places, resolved from the [`TerrainRegistry`].
+#[derive(Debug, Clone, Copy)]
+pub struct TerrainPalette {
+    terrain: [TerrainId; Biome::ALL.len()],
+}
+
+impl TerrainPalette {
+    /// Looks up the terrain kinds of all biomes.
+    /// Fails with the first terrain kind that is not registered.
+    pub fn resolve(registry: &TerrainRegistry) -> Result<Self, String> {
+        let mut terrain = [TerrainId::default(); Biome::ALL.len()];
+        for (slot, biome) in terrain.iter_mut().zip(Biome::ALL) {
+            *slot = registry.id(biome.terrain()).ok_or_else(|| {
+                format!(
+                    "terrain '{}' needed by the world generator is not registered",
+                    biome.terrain()
+                )
+            })?;
+        }
+        Ok(Self { terrain })
+    }
+
+    fn get(&self, biome: Biome) -> TerrainId {
+        self.terrain[biome as usize]
+    }
+}
+
+/// Terrain of every tile of a chunk, indexed by local `[x][y]`.
+pub type ChunkTerrain = [[TerrainId; CHUNK_SIZE]; CHUNK_SIZE];
+
+/// Generates the terrain of new chunks.
+///
+/// The terrain of a tile only depends on the seed and its global position,
+/// so chunks can be generated in any order and always produce the same world.
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct WorldGenerator {
+    seed: u64,
+}
+
+impl Default for WorldGenerator {
+    fn default() -> Self {
+        Self::new(DEFAULT_WORLD_SEED)
+    }
+}
+
+impl WorldGenerator {
+    /// Noise layer deciding between water, land and mountains.
+    const ELEVATION_LAYER: u64 = 0;
+    /// Noise layer deciding where forests grow.
+    const MOISTURE_LAYER: u64 = 1;
+    /// Size of the largest terrain features, in tiles.
+    const ELEVATION_SCALE: f32 = 64.0;
+    const MOISTURE_SCALE: f32 = 24.0;
+    const OCTAVES: u32 = 4;
+
+    const WATER_LEVEL: f32 = 0.38;
+    const CLIFF_LEVEL: f32 = 0.64;
+    const ROCK_LEVEL: f32 = 0.68;
+    const FOREST_MOISTURE: f32 = 0.6;
+
+    pub const fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    pub const fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Biome of the tile at the given global position.
+    fn biome_at(&self, global_pos: IVec2) -> Biome {
+        let position = global_pos.as_vec2();
+        let elevation = self.fractal_noise(Self::ELEVATION_LAYER, position / Self::ELEVATION_SCALE);
+        if elevation < Self::WATER_LEVEL {
+            return Biome::Water;
+        }
+        if elevation >= Self::ROCK_LEVEL {
+            return Biome::Rock;
+        }
+        if elevation >= Self::CLIFF_LEVEL {
+            return Biome::Cliffs;
+        }
+        let moisture = self.fractal_noise(Self::MOISTURE_LAYER, position / Self::MOISTURE_SCALE);
+        if moisture >= Self::FOREST_MOISTURE {
+            Biome::Forest
+        } else {
+            Biome::Grass
+        }
+    }
+
+    /// Terrain of every tile of the chunk at `chunk_pos`.
+    pub fn generate_chunk(&self, chunk_pos: IVec2, palette: &TerrainPalette) -> ChunkTerrain {
+        let mut terrain = [[TerrainId::default(); CHUNK_SIZE]; CHUNK_SIZE];
+        for (x, column) in terrain.iter_mut().enumerate() {
+            for (y, tile) in column.iter_mut().enumerate() {
+                let local_pos = IVec2::new(x as i32, y as i32);
+                *tile = palette.get(self.biome_at(Map::chunk_to_global(chunk_pos, local_pos)));
+            }
+        }
+        terrain
+    }
+
+    /// Sum of several octaves of value noise, in `0.0..1.0`.
+    fn fractal_noise(&self, layer: u64, position: Vec2) -> f32 {
+        let mut sum = 0.0;
+        let mut amplitude = 1.0;
+        let mut total_amplitude = 0.0;
+        let mut frequency = 1.0;
+        for octave in 0..Self::OCTAVES {
+            let octave_layer = layer * u64::from(Self::OCTAVES) + u64::from(octave);
+            sum += self.value_noise(octave_layer, position * frequency) * amplitude;
+            total_amplitude += amplitude;
+            amplitude *= 0.5;
+            frequency *= 2.0;
+        }
+        sum / total_amplitude
+    }
+
+    /// Smoothly interpolated random values at integer lattice points, in `0.0..1.0`.
+    fn value_noise(&self, layer: u64, position: Vec2) -> f32 {
+        let cell = position.floor();
+        let fraction = position - cell;
+        let cell = cell.as_ivec2();
+        // smoothstep removes the visible grid of linear interpolation
+        let weight = fraction * fraction * (Vec2::splat(3.0) - 2.0 * fraction);
+
+        let corner = |offset: IVec2| self.lattice_value(layer, cell + offset);
+        let bottom = corner(IVec2::new(0, 0)).lerp(corner(IVec2::new(1, 0)), weight.x);
+        let top = corner(IVec2::new(0, 1)).lerp(corner(IVec2::new(1, 1)), weight.x);
+        bottom.lerp(top, weight.y)
+    }
+
+    /// Random value of a lattice point, in `0.0..1.0`.
+    fn lattice_value(&self, layer: u64, point: IVec2) -> f32 {
+        let mut hash = self.seed ^ layer.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        hash = split_mix(hash ^ (point.x as u32 as u64));
+        hash = split_mix(hash ^ ((point.y as u32 as u64) << 32));
+        // the upper 24 bits fit into the mantissa of an f32 exactly
+        (hash >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+/// SplitMix64 finalizer, scrambling all bits of `value`.
+fn split_mix(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+pub struct TerrainPlugin;
+
+impl Plugin for TerrainPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<TerrainDefinitions>()
+            .register_asset_loader(RonAssetLoader::<TerrainDefinitions>::new(&["terrain.ron"]))
+            .init_resource::<TerrainRegistry>()
+            .init_resource::<WorldGenerator>();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::CHUNK_SIZE_I32;
+
+    /// Registry with a terrain kind for every biome, leaving out `missing`.
+    fn biome_terrain(missing: Option<&str>) -> TerrainRegistry {
+        let mut registry = TerrainRegistry::default();
+        for biome in Biome::ALL {
+            if Some(biome.terrain()) == missing {
+                continue;
+            }
+            registry
+                .register(TerrainKind {
+                    id: biome.terrain().to_string(),
+                    color: (0.0, 0.0, 0.0),
+                    buildable: true,
+                    walkable: true,
+                    movement_cost: 1.0,
+                    blocks_vision: false,
+                })
+                .unwrap();
+        }
+        registry
+    }
+
+    fn chunk_positions() -> Vec<IVec2> {
+        (-2..=2)
+            .flat_map(|x| (-2..=2).map(move |y| IVec2::new(x, y)))
+            .collect()
+    }
+
+    #[test]
+    fn chunks_do_not_depend_on_generation_order() {
+        let palette = TerrainPalette::resolve(&biome_terrain(None)).unwrap();
+        let positions = chunk_positions();
+        let in_order: HashMap<IVec2, ChunkTerrain> = positions
+            .iter()
+            .map(|&pos| (pos, WorldGenerator::new(7).generate_chunk(pos, &palette)))
+            .collect();
+        let generator = WorldGenerator::new(7);
+        for &pos in positions.iter().rev() {
+            assert_eq!(generator.generate_chunk(pos, &palette), in_order[&pos]);
+        }
+    }
+
+    #[test]
+    fn seeds_generate_different_worlds() {
+        let palette = TerrainPalette::resolve(&biome_terrain(None)).unwrap();
+        let world = |seed| {
+            let generator = WorldGenerator::new(seed);
+            chunk_positions()
+                .into_iter()
+                .map(|pos| generator.generate_chunk(pos, &palette))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(world(1), world(1));
+        assert_ne!(world(1), world(2));
+    }
+
+    #[test]
+    fn terrain_is_continuous_across_chunk_borders() {
+        let palette = TerrainPalette::resolve(&biome_terrain(None)).unwrap();
+        let generator = WorldGenerator::default();
+        for chunk_pos in chunk_positions() {
+            let chunk = generator.generate_chunk(chunk_pos, &palette);
+            for x in 0..CHUNK_SIZE_I32 {
+                for y in 0..CHUNK_SIZE_I32 {
+                    let global_pos = Map::chunk_to_global(chunk_pos, IVec2::new(x, y));
+                    assert_eq!(
+                        chunk[x as usize][y as usize],
+                        palette.get(generator.biome_at(global_pos)),
+                        "tile {} differs from the global sample",
+                        global_pos
+                    );
+                }
+            }
+        }
+        // tiles next to each other on both sides of a border usually share a biome
+        let matching = (0..CHUNK_SIZE_I32 * 8)
+            .filter(|&y| {
+                generator.biome_at(IVec2::new(CHUNK_SIZE_I32 - 1, y))
+                    == generator.biome_at(IVec2::new(CHUNK_SIZE_I32, y))
+            })
+            .count();
+        assert!(matching as i32 > CHUNK_SIZE_I32 * 6);
+    }
+
+    #[test]
+    fn palette_requires_core_terrain() {
+        let error = TerrainPalette::resolve(&biome_terrain(Some("core:forest"))).unwrap_err();
+        assert_eq!(
+            error,
+            "terrain 'core:forest' needed by the world generator is not registered"
+        );
+    }
+}
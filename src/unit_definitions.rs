@@ -0,0 +1,153 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+use serde::Deserialize;
+
+use crate::{
+    graphics::create_polygon_mesh,
+    map::FIELD_SIZE,
+    module_loader::RonAssetLoader,
+    units::{UnitBuilder, UnitEntry},
+};
+
+fn default_sides() -> usize {
+    12
+}
+
+/// Unit definition as written in a `*.unit.ron` asset file of a content module.
+#[derive(Asset, TypePath, Debug, Clone, Deserialize)]
+pub struct UnitDefinition {
+    /// Namespaced registry id, e.g. `core:worker`.
+    pub id: String,
+    /// Radius of the unit in tiles, used for its mesh and for picking it with the mouse.
+    pub radius: f32,
+    /// Number of corners of the polygon the unit is rendered as.
+    #[serde(default = "default_sides")]
+    pub sides: usize,
+    /// Color as `(red, green, blue)` in sRGB.
+    pub color: (f32, f32, f32),
+    /// Movement speed in tiles per second on terrain with a movement cost of 1.
+    pub speed: f32,
+    pub health: u32,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub cost: u32,
+    /// Training time in seconds.
+    #[serde(default)]
+    pub build_time: f32,
+    /// Control panel command that trains the unit, e.g. `core:train_infantry`.
+    #[serde(default)]
+    pub train_command: Option<String>,
+}
+
+impl UnitDefinition {
+    /// Checks the definition for problems that parsing alone cannot catch.
+    /// Returns a list of human readable problems if the definition is invalid.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+        match self.id.split_once(':') {
+            Some((namespace, name)) if !namespace.is_empty() && !name.is_empty() => {}
+            _ => problems.push(format!(
+                "id '{}' must have the form 'namespace:name'",
+                self.id
+            )),
+        }
+        if self.radius <= 0.0 {
+            problems.push(format!("radius must be positive, got {}", self.radius));
+        }
+        if self.sides < 3 {
+            problems.push(format!(
+                "polygon needs at least 3 sides, got {}",
+                self.sides
+            ));
+        }
+        if self.speed <= 0.0 {
+            problems.push(format!("speed must be positive, got {}", self.speed));
+        }
+        if self.health == 0 {
+            problems.push("health must be positive".to_string());
+        }
+        if let Some(command) = &self.train_command {
+            match command.split_once(':') {
+                Some((namespace, name)) if !namespace.is_empty() && !name.is_empty() => {}
+                _ => problems.push(format!(
+                    "train command '{}' must have the form 'namespace:name'",
+                    command
+                )),
+            }
+        }
+        if self.build_time < 0.0 {
+            problems.push(format!(
+                "build time must not be negative, got {}",
+                self.build_time
+            ));
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+
+    /// Creates the registry entry for this definition.
+    /// The definition must have passed [`UnitDefinition::validate`].
+    fn to_entry(
+        &self,
+        meshes: &mut Assets<Mesh>,
+        materials: &mut Assets<ColorMaterial>,
+    ) -> UnitEntry {
+        let (red, green, blue) = self.color;
+        UnitEntry {
+            mesh_handle: meshes.add(create_polygon_mesh(self.sides, self.radius * FIELD_SIZE)),
+            material_handle: materials
+                .add(ColorMaterial::from_color(Color::srgb(red, green, blue))),
+            speed: self.speed,
+            health: self.health,
+            radius: self.radius,
+            description: self.description.clone(),
+            cost: self.cost,
+            build_time: self.build_time,
+            train_command: self.train_command.clone(),
+            builder: Box::new(DefinitionBuilder),
+        }
+    }
+}
+
+/// Builder for units created from a [`UnitDefinition`].
+struct DefinitionBuilder;
+
+impl UnitBuilder for DefinitionBuilder {
+    fn build(&self, entry: &UnitEntry, commands: &mut Commands, position: Vec2) -> Entity {
+        commands
+            .spawn((
+                entry.transform(position),
+                GlobalTransform::default(),
+                Mesh2d(entry.mesh_handle.clone()),
+                MeshMaterial2d(entry.material_handle.clone()),
+            ))
+            .id()
+    }
+}
+
+/// Everything needed to turn a [`UnitDefinition`] into a [`UnitEntry`].
+#[derive(SystemParam)]
+pub struct UnitEntryFactory<'w> {
+    meshes: ResMut<'w, Assets<Mesh>>,
+    materials: ResMut<'w, Assets<ColorMaterial>>,
+}
+
+impl UnitEntryFactory<'_> {
+    /// Creates the registry entry of a definition, adding its mesh and material.
+    /// The definition must have passed [`UnitDefinition::validate`].
+    pub fn create(&mut self, definition: &UnitDefinition) -> UnitEntry {
+        definition.to_entry(&mut self.meshes, &mut self.materials)
+    }
+}
+
+pub struct UnitDefinitionsPlugin;
+
+impl Plugin for UnitDefinitionsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<UnitDefinition>()
+            .register_asset_loader(RonAssetLoader::<UnitDefinition>::new(&["unit.ron"]));
+    }
+}
@@ -0,0 +1,524 @@
+use std::collections::{HashMap, HashSet, hash_map::Entry};
+
+use bevy::prelude::*;
+
+use crate::{
+    AppState,
+    flow_field::{FlowFieldTarget, FlowFields},
+    map::{FIELD_SIZE, Map},
+    module_loader::modules_applied,
+    pathfinding::{Path, PathTarget, movement_cost},
+    terrain::TerrainRegistry,
+    toasts::ToastMessage,
+};
+
+/// Depth of units, above buildings.
+pub const UNIT_Z: f32 = 1.0;
+
+/// Trait for unit spawning logic.
+pub trait UnitBuilder: Send + Sync + 'static {
+    /// Spawns the unit at the world `position` and returns the spawned entity.
+    fn build(&self, entry: &UnitEntry, commands: &mut Commands, position: Vec2) -> Entity;
+}
+
+impl<F> UnitBuilder for F
+where
+    F: Fn(&UnitEntry, &mut Commands, Vec2) -> Entity + Send + Sync + 'static,
+{
+    fn build(&self, entry: &UnitEntry, commands: &mut Commands, position: Vec2) -> Entity {
+        (self)(entry, commands, position)
+    }
+}
+
+pub struct UnitEntry {
+    pub mesh_handle: Handle<Mesh>,
+    pub material_handle: Handle<ColorMaterial>,
+    /// Movement speed in tiles per second on terrain with a movement cost of 1.
+    pub speed: f32,
+    pub health: u32,
+    /// Radius in tiles.
+    pub radius: f32,
+    pub description: Option<String>,
+    pub cost: u32,
+    /// Training time in seconds.
+    pub build_time: f32,
+    /// Control panel command that trains the unit.
+    pub train_command: Option<String>,
+    pub builder: Box<dyn UnitBuilder>,
+}
+
+impl UnitEntry {
+    /// Transform of the unit at the world `position`.
+    pub fn transform(&self, position: Vec2) -> Transform {
+        Transform::from_translation(position.extend(UNIT_Z))
+    }
+}
+
+impl std::fmt::Debug for UnitEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UnitEntry")
+            .field("mesh_handle", &self.mesh_handle)
+            .field("material_handle", &self.material_handle)
+            .field("speed", &self.speed)
+            .field("health", &self.health)
+            .field("radius", &self.radius)
+            .field("description", &self.description)
+            .field("cost", &self.cost)
+            .field("build_time", &self.build_time)
+            .field("train_command", &self.train_command)
+            .finish()
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct UnitRegistry {
+    pub units: HashMap<String, UnitEntry>,
+}
+
+impl UnitRegistry {
+    /// Registers a new unit.
+    /// Fails if a unit with the same id is already registered;
+    /// use [`UnitRegistry::replace`] to override it explicitly.
+    pub fn register(&mut self, id: impl Into<String>, entry: UnitEntry) -> Result<(), String> {
+        let id = id.into();
+        match self.units.entry(id) {
+            Entry::Vacant(e) => {
+                info!("Registering unit: {} -> {:?}", e.key(), entry);
+                e.insert(entry);
+                Ok(())
+            }
+            Entry::Occupied(e) => Err(format!("unit '{}' is already registered", e.key())),
+        }
+    }
+
+    /// Replaces an already registered unit and returns the previous entry.
+    /// Returns `None` and registers nothing if no unit with that id exists.
+    pub fn replace(&mut self, id: &str, entry: UnitEntry) -> Option<UnitEntry> {
+        let existing = self.units.get_mut(id)?;
+        info!("Replacing unit: {} -> {:?}", id, entry);
+        Some(std::mem::replace(existing, entry))
+    }
+
+    /// Spawns the unit `id` at the world `position` with full health.
+    /// Returns `None` if no unit with that id is registered.
+    pub fn spawn(&self, id: &str, position: Vec2, commands: &mut Commands) -> Option<Entity> {
+        let entry = self.units.get(id)?;
+        let entity = entry.builder.build(entry, commands, position);
+        commands.entity(entity).insert((
+            Unit { id: id.to_string() },
+            Health {
+                current: entry.health,
+                max: entry.health,
+            },
+        ));
+        Some(entity)
+    }
+}
+
+/// Marks an entity as a unit spawned from the [`UnitRegistry`].
+#[derive(Component, Debug, Clone)]
+pub struct Unit {
+    /// Registry id of the unit.
+    pub id: String,
+}
+
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Health {
+    pub current: u32,
+    pub max: u32,
+}
+
+/// Sent after the [`UnitRegistry`] entry of a unit was replaced,
+/// e.g. by a reloaded definition file.
+#[derive(Message, Debug, Clone)]
+pub struct UnitEntryReplaced {
+    pub id: String,
+}
+
+type RefreshedUnitData<'a> = (
+    &'a Unit,
+    &'a mut Mesh2d,
+    &'a mut MeshMaterial2d<ColorMaterial>,
+    &'a mut Health,
+);
+
+/// Gives spawned units the mesh, material and health of their replaced registry entry.
+fn refresh_units(
+    mut replaced: MessageReader<UnitEntryReplaced>,
+    registry: Res<UnitRegistry>,
+    mut units: Query<RefreshedUnitData>,
+) {
+    let ids: HashSet<String> = replaced.read().map(|event| event.id.clone()).collect();
+    for (unit, mut mesh, mut material, mut health) in &mut units {
+        if !ids.contains(&unit.id) {
+            continue;
+        }
+        let Some(entry) = registry.units.get(&unit.id) else {
+            continue;
+        };
+        mesh.0 = entry.mesh_handle.clone();
+        material.0 = entry.material_handle.clone();
+        health.max = entry.health;
+        health.current = health.current.min(health.max);
+    }
+}
+
+/// Tiles around a footprint tile that a trained unit may appear on.
+const NEIGHBORS: [IVec2; 8] = [
+    IVec2::X,
+    IVec2::NEG_X,
+    IVec2::Y,
+    IVec2::NEG_Y,
+    IVec2::new(1, 1),
+    IVec2::new(1, -1),
+    IVec2::new(-1, 1),
+    IVec2::new(-1, -1),
+];
+
+/// Units every new game starts with, next to the world origin.
+const STARTING_UNITS: [&str; 5] = [
+    "core:worker",
+    "core:worker",
+    "core:worker",
+    "core:infantry",
+    "core:infantry",
+];
+
+/// Returns up to `count` walkable, unoccupied tiles, searching outwards from `center`
+/// in rings of growing distance.
+pub fn free_tiles_around(
+    map: &Map,
+    terrain: &TerrainRegistry,
+    center: IVec2,
+    count: usize,
+    max_distance: i32,
+) -> Vec<IVec2> {
+    let mut tiles = Vec::with_capacity(count);
+    for distance in 0..=max_distance {
+        for x in -distance..=distance {
+            for y in -distance..=distance {
+                if x.abs().max(y.abs()) != distance {
+                    continue;
+                }
+                let tile = center + IVec2::new(x, y);
+                if movement_cost(map, terrain, tile).is_some() {
+                    tiles.push(tile);
+                    if tiles.len() == count {
+                        return tiles;
+                    }
+                }
+            }
+        }
+    }
+    tiles
+}
+
+/// First walkable, unoccupied tile bordering the footprint.
+/// Tiles are tried row by row from the bottom left, so units always leave a building
+/// at the same spot while it is free.
+pub fn free_tile_next_to(
+    map: &Map,
+    terrain: &TerrainRegistry,
+    footprint: &[IVec2],
+) -> Option<IVec2> {
+    let mut tiles: Vec<IVec2> = footprint
+        .iter()
+        .flat_map(|&tile| NEIGHBORS.map(|direction| tile + direction))
+        .filter(|tile| !footprint.contains(tile))
+        .collect();
+    tiles.sort_by_key(|tile| (tile.y, tile.x));
+    tiles.dedup();
+    tiles
+        .into_iter()
+        .find(|&tile| movement_cost(map, terrain, tile).is_some())
+}
+
+/// Spawns the [`STARTING_UNITS`] once the chunk at the world origin was loaded.
+fn spawn_starting_units(
+    mut commands: Commands,
+    mut spawned: Local<bool>,
+    map: Res<Map>,
+    terrain: Res<TerrainRegistry>,
+    registry: Res<UnitRegistry>,
+    mut toasts: MessageWriter<ToastMessage>,
+) {
+    if *spawned || map.chunk_entity(IVec2::ZERO).is_none() {
+        return;
+    }
+    *spawned = true;
+    let tiles = free_tiles_around(&map, &terrain, IVec2::ZERO, STARTING_UNITS.len(), 8);
+    let mut count = 0;
+    for (id, tile) in STARTING_UNITS.iter().zip(tiles) {
+        if registry
+            .spawn(id, Map::tile_center(tile), &mut commands)
+            .is_some()
+        {
+            count += 1;
+        } else {
+            warn!("Cannot spawn unknown starting unit '{}'", id);
+        }
+    }
+    toasts.write(ToastMessage {
+        content: format!("Spawned {} starting units", count),
+    });
+}
+
+/// Makes a unit move next to another entity and stay with it while it moves.
+/// Removed when the target is despawned.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Follow {
+    pub target: Entity,
+    /// Tile the unit was last sent to, so it is not sent there again after it arrived
+    /// or found the tile unreachable.
+    goal: Option<IVec2>,
+}
+
+impl Follow {
+    pub fn new(target: Entity) -> Self {
+        Self { target, goal: None }
+    }
+}
+
+/// Points the [`PathTarget`] of following units at their target: at a free tile next to
+/// the footprint of buildings, at the tile of everything else.
+fn follow_targets(
+    mut commands: Commands,
+    map: Res<Map>,
+    terrain: Res<TerrainRegistry>,
+    mut followers: Query<(Entity, &mut Follow)>,
+    targets: Query<&Transform>,
+) {
+    for (entity, mut follow) in &mut followers {
+        let Ok(target_transform) = targets.get(follow.target) else {
+            commands
+                .entity(entity)
+                .remove::<(Follow, Path, PathTarget)>();
+            continue;
+        };
+        let goal = match map.footprint(follow.target) {
+            Some(footprint) => {
+                let Some(tile) = free_tile_next_to(&map, &terrain, footprint) else {
+                    continue;
+                };
+                tile
+            }
+            None => Map::world_to_global(target_transform.translation.truncate()),
+        };
+        if follow.goal == Some(goal) {
+            continue;
+        }
+        follow.goal = Some(goal);
+        commands
+            .entity(entity)
+            .remove::<Path>()
+            .insert(PathTarget { goal });
+    }
+}
+
+type MovingUnitData<'a> = (
+    Entity,
+    &'a Unit,
+    &'a mut Transform,
+    Option<&'a Path>,
+    Option<&'a FlowFieldTarget>,
+);
+
+/// Moves units along their [`Path`], or along the shared flow field of their
+/// [`FlowFieldTarget`], and drops the target once they arrived.
+///
+/// Units are slowed down by the movement cost of the terrain they stand on.
+fn move_units(
+    mut commands: Commands,
+    time: Res<Time>,
+    map: Res<Map>,
+    terrain: Res<TerrainRegistry>,
+    registry: Res<UnitRegistry>,
+    flow_fields: Res<FlowFields>,
+    mut units: Query<MovingUnitData>,
+) {
+    for (entity, unit, mut transform, path, flow_field_target) in &mut units {
+        let position = transform.translation.truncate();
+        let tile = Map::world_to_global(position);
+        // tile whose center the unit walks to next, `None` once it arrived
+        let next_tile = if let Some(path) = path {
+            match path.tiles.iter().position(|&path_tile| path_tile == tile) {
+                Some(index) if index + 1 < path.tiles.len() => Some(path.tiles[index + 1]),
+                Some(_) => None,
+                // unreachable goal, the path is searched again once the map changes
+                None if path.tiles.is_empty() => continue,
+                // pushed off the path, a new one is searched from the current tile
+                None => {
+                    commands.entity(entity).remove::<Path>();
+                    continue;
+                }
+            }
+        } else if let Some(target) = flow_field_target {
+            // the flow field is built once per goal, units wait for it
+            let Some(field) = flow_fields.get(target.goal) else {
+                continue;
+            };
+            match field.direction(tile) {
+                Some(direction) => Some(tile + direction),
+                None if field.cost(tile).is_some() => None,
+                None => {
+                    // the goal cannot be reached from here
+                    commands.entity(entity).remove::<FlowFieldTarget>();
+                    continue;
+                }
+            }
+        } else {
+            continue;
+        };
+
+        let Some(entry) = registry.units.get(&unit.id) else {
+            continue;
+        };
+        let tile_cost = movement_cost(&map, &terrain, tile).unwrap_or(1.0);
+        let step = entry.speed * FIELD_SIZE / tile_cost * time.delta_secs();
+        let destination = Map::tile_center(next_tile.unwrap_or(tile));
+        let offset = destination - position;
+        if offset.length() <= step {
+            transform.translation = destination.extend(transform.translation.z);
+            if next_tile.is_none() {
+                commands
+                    .entity(entity)
+                    .remove::<(Path, PathTarget, FlowFieldTarget)>();
+            }
+        } else {
+            transform.translation += (offset.normalize() * step).extend(0.0);
+        }
+    }
+}
+
+pub struct UnitsPlugin;
+
+impl Plugin for UnitsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<UnitRegistry>()
+            .add_message::<UnitEntryReplaced>()
+            .add_systems(
+                Update,
+                (
+                    refresh_units,
+                    spawn_starting_units.run_if(modules_applied),
+                    follow_targets,
+                    move_units,
+                )
+                    .run_if(in_state(AppState::Game)),
+            );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::{
+        map::CHUNK_SIZE_I32,
+        pathfinding::{
+            compute_paths, invalidate_paths,
+            tests::{grass, load_test_chunk, test_map, test_terrain},
+        },
+    };
+
+    /// World with the resources [`move_units`] needs and one unit at `tile`.
+    fn world_with_unit(tile: IVec2, path: Path) -> (World, Entity) {
+        let mut world = World::new();
+        world.insert_resource(Time::<()>::default());
+        world.init_resource::<Map>();
+        world.init_resource::<TerrainRegistry>();
+        world.init_resource::<UnitRegistry>();
+        world.init_resource::<FlowFields>();
+        let unit = world
+            .spawn((
+                Unit {
+                    id: "test:unit".to_string(),
+                },
+                Transform::from_translation(Map::tile_center(tile).extend(UNIT_Z)),
+                path,
+                PathTarget {
+                    goal: IVec2::new(6, 0),
+                },
+            ))
+            .id();
+        (world, unit)
+    }
+
+    #[test]
+    fn units_pushed_off_their_path_search_a_new_one() {
+        let path = Path {
+            tiles: (0..=6).map(|x| IVec2::new(x, 0)).collect(),
+        };
+        let (mut world, unit) = world_with_unit(IVec2::new(3, 2), path);
+        world.run_system_once(move_units).unwrap();
+
+        let unit = world.entity(unit);
+        assert!(!unit.contains::<Path>());
+        assert!(unit.contains::<PathTarget>());
+    }
+
+    #[test]
+    fn units_with_unreachable_goals_keep_waiting() {
+        let (mut world, unit) = world_with_unit(IVec2::new(3, 2), Path::default());
+        world.run_system_once(move_units).unwrap();
+
+        let unit = world.entity(unit);
+        assert!(unit.get::<Path>().is_some_and(|path| path.tiles.is_empty()));
+        assert!(unit.contains::<PathTarget>());
+    }
+
+    #[test]
+    fn units_waiting_on_unloaded_goals_walk_once_the_chunk_loads() {
+        let start = IVec2::new(2, 2);
+        let (mut world, unit) = world_with_unit(start, Path::default());
+        let terrain = test_terrain(1.0);
+        world.insert_resource(test_map(&terrain, &[IVec2::ZERO], grass, &[]));
+        world.insert_resource(terrain);
+        world.entity_mut(unit).insert(PathTarget {
+            goal: IVec2::new(CHUNK_SIZE_I32 + 4, 2),
+        });
+        world
+            .resource_mut::<UnitRegistry>()
+            .register(
+                "test:unit",
+                UnitEntry {
+                    mesh_handle: Handle::default(),
+                    material_handle: Handle::default(),
+                    speed: 1.0,
+                    health: 1,
+                    radius: 0.5,
+                    description: None,
+                    cost: 0,
+                    build_time: 0.0,
+                    train_command: None,
+                    builder: Box::new(|_: &UnitEntry, commands: &mut Commands, _: Vec2| {
+                        commands.spawn_empty().id()
+                    }),
+                },
+            )
+            .unwrap();
+        world
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_millis(100));
+        let step = |world: &mut World| {
+            world.run_system_once(invalidate_paths).unwrap();
+            world.run_system_once(compute_paths).unwrap();
+            world.run_system_once(move_units).unwrap();
+            Map::world_to_global(world.get::<Transform>(unit).unwrap().translation.truncate())
+        };
+
+        assert_eq!(step(&mut world), start);
+        assert!(world.get::<Path>(unit).unwrap().tiles.is_empty());
+
+        world.resource_scope(|world, mut map: Mut<Map>| {
+            let terrain = world.resource::<TerrainRegistry>();
+            load_test_chunk(&mut map, terrain, IVec2::new(1, 0), grass);
+        });
+        step(&mut world);
+        let position = world.get::<Transform>(unit).unwrap().translation.truncate();
+        assert!(position.x > Map::tile_center(start).x);
+    }
+}
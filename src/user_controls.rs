@@ -1,6 +1,19 @@
 use std::collections::{HashMap, hash_map::Entry};
 
-use bevy::prelude::*;
+use bevy::{ecs::system::SystemParam, prelude::*};
+use serde::Deserialize;
+
+use crate::{
+    AppState, InputMode, MouseCursor,
+    build_mode::EnterBuildMode,
+    flow_field::FlowFieldTarget,
+    key_bindings,
+    map::{FIELD_SIZE, Map},
+    module_loader::RonAssetLoader,
+    pathfinding::{Path, PathTarget},
+    selection::{Selectables, Selection},
+    units::{Follow, Unit, UnitRegistry},
+};
 
 #[derive(Debug, Clone)]
 pub enum CommandPayload {
@@ -13,14 +26,16 @@ pub enum CommandPayload {
 pub struct CommandEvent {
     pub command_type: String,
     pub payload: CommandPayload,
+    /// Entities the command was issued to.
+    pub issuers: Vec<Entity>,
 }
 
 /// This tells the input system how to handle user input for a specific command.
 /// Some commands require targeting (e.g., attack command needs a target entity),
 /// while others can be executed immediately (e.g., stop command).
 /// This is a polymorphic behavior that can be extended for different command types.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum CommandInputMode {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum CommandInputMode {
     /// Command is executed immediately without targeting.
     /// Results in `CommandPayload::None`.
     /// Examples include stop or hold position commands.
@@ -43,18 +58,23 @@ enum CommandInputMode {
     SelectTargetedPointOrEntity,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-enum PanelTransition {
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub enum PanelTransition {
     Push(String),
     Pop,
 }
 
-#[derive(Debug, Clone)]
-struct CommandEntry {
-    command_type: String,
-    input_mode: CommandInputMode,
+#[derive(Debug, Clone, Deserialize)]
+pub struct CommandEntry {
+    pub command_type: String,
+    pub input_mode: CommandInputMode,
 }
 
+/// Command entries as written in a `*.commands.ron` asset file of a content module.
+#[derive(Asset, TypePath, Debug, Clone, Deserialize)]
+#[serde(transparent)]
+pub struct CommandDefinitions(pub Vec<CommandEntry>);
+
 #[derive(Resource, Default)]
 pub struct CommandRegistry {
     behaviors: HashMap<String, CommandEntry>,
@@ -64,7 +84,7 @@ impl CommandRegistry {
     /// Registers a new command behavior.
     /// If a behavior for the same command type already exists,
     /// it will be overwritten, but a warning will be logged.
-    fn register(&mut self, behavior: CommandEntry) {
+    pub fn register(&mut self, behavior: CommandEntry) {
         match self.behaviors.entry(behavior.command_type.clone()) {
             Entry::Vacant(e) => {
                 e.insert(behavior);
@@ -87,8 +107,8 @@ impl CommandRegistry {
 }
 
 /// Action associated with a control panel entry.
-#[derive(Debug, Clone)]
-enum ControlPanelAction {
+#[derive(Debug, Clone, Deserialize)]
+pub enum ControlPanelAction {
     /// Execute a command identified by its command ID.
     ExecuteCommand(String),
     /// Transition to another control panel state.
@@ -97,18 +117,21 @@ enum ControlPanelAction {
         command_id: String,
         transition: PanelTransition,
     },
+    /// Select a building for placement, identified by its registry ID.
+    EnterBuildMode(String),
 }
 
 /// Control panel layout for entities.
-#[derive(Debug, Clone, Default)]
-struct ControlPanel {
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ControlPanel {
     /// 5x3 grid for commands. Each entry can be `Some(command_id)` or `None` for empty slots.
     /// To execute a command, the UI system will look up the command ID in the [`CommandRegistry`].
     entries: [[Option<ControlPanelAction>; 5]; 3],
 }
 
 /// Control panel tree for different entity states.
-struct ControlPanelTree {
+#[derive(Debug, Clone, Deserialize)]
+pub struct ControlPanelTree {
     /// Root panel identifier.
     root: String,
     /// Control panels for different states, identified by state name.
@@ -130,7 +153,7 @@ impl ControlPanelRegistry {
     /// Registers a control panel tree for a specific entity type.
     /// If a panel tree for the same entity type already exists,
     /// it will be overwritten, but a warning will be logged.
-    fn register(&mut self, entity_type: String, panel_tree: ControlPanelTree) {
+    pub fn register(&mut self, entity_type: String, panel_tree: ControlPanelTree) {
         match self.panels.entry(entity_type.clone()) {
             Entry::Vacant(e) => {
                 e.insert(panel_tree);
@@ -150,9 +173,79 @@ impl ControlPanelRegistry {
     }
 }
 
+/// Control panel trees as written in a `*.panels.ron` asset file of a content module,
+/// identified by entity type.
+#[derive(Asset, TypePath, Debug, Clone, Deserialize)]
+#[serde(transparent)]
+pub struct ControlPanelDefinitions(pub HashMap<String, ControlPanelTree>);
+
+/// What the control panel currently shows.
+#[derive(Resource, Default)]
+pub struct ControlPanelState {
+    /// Entity type whose panel tree is shown, `None` while the panel is empty.
+    entity_type: Option<String>,
+    /// Entities that commands from the panel are issued to.
+    entities: Vec<Entity>,
+    /// Ids of the opened panels of the tree, the last one is shown.
+    open_panels: Vec<String>,
+}
+
+impl ControlPanelState {
+    /// Shows the root panel of `entity_type` for the given entities.
+    pub fn show(
+        &mut self,
+        entity_type: &str,
+        entities: Vec<Entity>,
+        registry: &ControlPanelRegistry,
+    ) {
+        self.entity_type = Some(entity_type.to_string());
+        self.entities = entities;
+        self.open_panels = registry
+            .get(entity_type)
+            .map(|tree| vec![tree.root.clone()])
+            .unwrap_or_default();
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
+    fn current_panel<'a>(&self, registry: &'a ControlPanelRegistry) -> Option<&'a ControlPanel> {
+        let tree = registry.get(self.entity_type.as_deref()?)?;
+        tree.panels.get(self.open_panels.last()?)
+    }
+
+    fn transition(&mut self, transition: &PanelTransition, registry: &ControlPanelRegistry) {
+        match transition {
+            PanelTransition::Push(panel) => {
+                let exists = self
+                    .entity_type
+                    .as_deref()
+                    .and_then(|entity_type| registry.get(entity_type))
+                    .is_some_and(|tree| tree.panels.contains_key(panel));
+                if exists {
+                    self.open_panels.push(panel.clone());
+                } else {
+                    warn!("Cannot open unknown control panel '{}'", panel);
+                }
+            }
+            PanelTransition::Pop => {
+                if self.open_panels.len() > 1 {
+                    self.open_panels.pop();
+                }
+            }
+        }
+    }
+}
+
 pub trait CommandDispatcher: std::fmt::Debug + Send + Sync + 'static {
     fn catches(&self, command_type: &str) -> bool;
-    fn dispatch_command(&self, command_event: CommandEvent);
+    /// Carries out the command. Changes to the world are made through `commands`.
+    fn dispatch_command(&self, command_event: CommandEvent, commands: &mut Commands);
 }
 
 macro_rules! impl_command_dispatcher {
@@ -164,7 +257,7 @@ macro_rules! impl_command_dispatcher {
         {
             struct DispatcherImpl;
 
-            impl CommandDispatcher for DispatcherImpl {
+            impl $crate::user_controls::CommandDispatcher for DispatcherImpl {
                 fn catches(&self, command_type: &str) -> bool {
                     match command_type {
                         $(
@@ -174,8 +267,12 @@ macro_rules! impl_command_dispatcher {
                     }
                 }
 
-                fn dispatch_command(&self, command_event: CommandEvent) {
-                    ($dispatcher_fn)(command_event);
+                fn dispatch_command(
+                    &self,
+                    command_event: $crate::user_controls::CommandEvent,
+                    commands: &mut ::bevy::prelude::Commands,
+                ) {
+                    ($dispatcher_fn)(command_event, commands);
                 }
             }
 
@@ -185,84 +282,55 @@ macro_rules! impl_command_dispatcher {
                 }
             }
 
-            Box::new(DispatcherImpl) as Box<dyn CommandDispatcher>
+            Box::new(DispatcherImpl) as Box<dyn $crate::user_controls::CommandDispatcher>
         }
     };
 }
 
+pub(crate) use impl_command_dispatcher;
+
 #[derive(Resource, Default)]
-struct CommandDispatcherPipeline {
+pub struct CommandDispatcherPipeline {
     dispatchers: Vec<Box<dyn CommandDispatcher>>,
 }
 
 impl CommandDispatcherPipeline {
-    fn dispatch(&self, command_event: CommandEvent) {
+    fn dispatch(&self, command_event: CommandEvent, commands: &mut Commands) {
         for dispatcher in &self.dispatchers {
             if dispatcher.catches(&command_event.command_type) {
-                dispatcher.dispatch_command(command_event.clone());
+                dispatcher.dispatch_command(command_event.clone(), commands);
             }
         }
     }
 
-    fn register_dispatcher(&mut self, dispatcher: Box<dyn CommandDispatcher>) {
+    pub fn register_dispatcher(&mut self, dispatcher: Box<dyn CommandDispatcher>) {
         info!("Registering command dispatcher: {:?}", dispatcher);
         self.dispatchers.push(dispatcher);
     }
 }
 
-fn setup_ui(
-    mut commands: Commands,
-    mut command_registry: ResMut<CommandRegistry>,
-    mut control_panel_registry: ResMut<ControlPanelRegistry>,
-    mut dispatcher_pipeline: ResMut<CommandDispatcherPipeline>,
-) {
-    const WORKER_ENTITY_TYPE: &str = "core:worker";
-    const MOVE_COMMAND_ID: &str = "core:move";
-
-    command_registry.register(CommandEntry {
-        command_type: MOVE_COMMAND_ID.to_string(),
-        input_mode: CommandInputMode::SelectTargetedPoint,
-    });
-    control_panel_registry.register(
-        WORKER_ENTITY_TYPE.to_string(),
-        ControlPanelTree {
-            root: "/".to_string(),
-            panels: {
-                let move_action = ControlPanelAction::ExecuteAndTransition {
-                    command_id: MOVE_COMMAND_ID.to_string(),
-                    transition: PanelTransition::Push("/build".to_string()),
-                };
-                let root_panel = ControlPanel {
-                    entries: [
-                        [Some(move_action), None, None, None, None],
-                        [None, None, None, None, None],
-                        [None, None, None, None, None],
-                    ],
-                };
-                let cancel_action = ControlPanelAction::ExecuteAndTransition {
-                    command_id: "core:cancel".to_string(),
-                    transition: PanelTransition::Pop,
-                };
-                let build_panel = ControlPanel {
-                    entries: [
-                        [None, None, None, None, Some(cancel_action)],
-                        [None, None, None, None, None],
-                        [None, None, None, None, None],
-                    ],
-                };
-                let mut panels = HashMap::new();
-                panels.insert("/".to_string(), root_panel);
-                panels.insert("/build".to_string(), build_panel);
-                panels
-            },
-        },
-    );
-
+fn setup_ui(mut commands: Commands, mut dispatcher_pipeline: ResMut<CommandDispatcherPipeline>) {
     let move_dispatcher = impl_command_dispatcher!(
         "MoveCommandDispatcher",
         ["core:move"],
-        |event: CommandEvent| {
-            info!("Dispatching move command: {:?}", event);
+        |event: CommandEvent, commands: &mut Commands| {
+            for &issuer in &event.issuers {
+                let Ok(mut entity) = commands.get_entity(issuer) else {
+                    continue;
+                };
+                entity.remove::<(Path, PathTarget, FlowFieldTarget, Follow)>();
+                match event.payload {
+                    CommandPayload::TargetPoint(point) => {
+                        entity.insert(FlowFieldTarget {
+                            goal: Map::world_to_global(point),
+                        });
+                    }
+                    CommandPayload::TargetEntity(target) => {
+                        entity.insert(Follow::new(target));
+                    }
+                    CommandPayload::None => {}
+                }
+            }
         },
     );
     dispatcher_pipeline.register_dispatcher(move_dispatcher);
@@ -288,7 +356,7 @@ fn setup_ui(
 }
 
 #[derive(Component)]
-struct ControlPanelMarker;
+pub struct ControlPanelMarker;
 
 #[derive(Component)]
 struct ControlPanelSlot {
@@ -296,6 +364,13 @@ struct ControlPanelSlot {
     column: u8,
 }
 
+/// Text naming the action of the [`ControlPanelSlot`] it belongs to.
+#[derive(Component)]
+struct ControlPanelSlotLabel {
+    row: u8,
+    column: u8,
+}
+
 const CONTROL_PANEL_SLOT_COLOR_NORMAL: Color = Color::srgb(0.3, 0.3, 0.3);
 const CONTROL_PANEL_SLOT_COLOR_HOVER: Color = Color::srgb(0.5, 0.5, 0.5);
 const CONTROL_PANEL_SLOT_COLOR_ACTIVE: Color = Color::srgb(0.8, 0.8, 0.2);
@@ -314,6 +389,11 @@ fn render_control_panel() -> impl Bundle {
             },
             BackgroundColor(Color::srgb(0.3, 0.3, 0.3)),
             ControlPanelSlot { row, column },
+            children![(
+                Text::default(),
+                TextFont::from_font_size(8.0),
+                ControlPanelSlotLabel { row, column },
+            )],
         )
     }
 
@@ -340,26 +420,253 @@ fn render_control_panel() -> impl Bundle {
             BackgroundColor(Color::srgb(0.1, 0.1, 0.1)),
             BorderColor::all(Color::srgb(0.5, 0.5, 0.5)),
             BorderRadius::all(px(10.0)),
+            // lets clicks on the panel be told apart from clicks on the map
+            Interaction::default(),
             ControlPanelMarker,
         ),
         children,
     )
 }
 
+impl ControlPanelAction {
+    /// Short name shown on the control panel slot.
+    fn label(&self) -> &str {
+        let command_id = match self {
+            Self::ExecuteCommand(command_id) => command_id,
+            Self::ExecuteAndTransition { command_id, .. } => command_id,
+            Self::EnterBuildMode(building_id) => building_id,
+            Self::TransitionPanel(PanelTransition::Push(panel)) => return panel,
+            Self::TransitionPanel(PanelTransition::Pop) => return "back",
+        };
+        command_id
+            .split_once(':')
+            .map_or(command_id.as_str(), |(_, name)| name)
+    }
+}
+
+/// Whether the cursor is above the control panel, so clicks do not reach the map.
+pub fn control_panel_hovered(panel: Single<&Interaction, With<ControlPanelMarker>>) -> bool {
+    **panel != Interaction::None
+}
+
+/// Shows the control panel of the first selected entity. Its commands are issued to every
+/// selected entity of the same type.
+fn show_selection_in_control_panel(
+    selection: Res<Selection>,
+    selectables: Selectables,
+    registry: Res<ControlPanelRegistry>,
+    mut state: ResMut<ControlPanelState>,
+) {
+    if !selection.is_changed() {
+        return;
+    }
+    let Some(entity_type) = selection
+        .entities()
+        .iter()
+        .find_map(|&entity| selectables.type_of(entity))
+    else {
+        state.clear();
+        return;
+    };
+    let entities = selection
+        .entities()
+        .iter()
+        .copied()
+        .filter(|&entity| selectables.type_of(entity) == Some(entity_type))
+        .collect();
+    state.show(entity_type, entities, &registry);
+}
+
+fn update_control_panel_labels(
+    state: Res<ControlPanelState>,
+    registry: Res<ControlPanelRegistry>,
+    mut labels: Query<(&ControlPanelSlotLabel, &mut Text)>,
+) {
+    if !state.is_changed() && !registry.is_changed() {
+        return;
+    }
+    let panel = state.current_panel(&registry);
+    for (label, mut text) in &mut labels {
+        let action = panel
+            .and_then(|panel| panel.entries[label.row as usize][label.column as usize].as_ref());
+        text.0 = action
+            .map(|action| action.label().to_string())
+            .unwrap_or_default();
+    }
+}
+
+/// Finds the entity under a world position.
+#[derive(SystemParam)]
+pub struct EntityPicker<'w, 's> {
+    map: Res<'w, Map>,
+    unit_registry: Res<'w, UnitRegistry>,
+    units: Query<'w, 's, (Entity, &'static Unit, &'static Transform)>,
+}
+
+impl EntityPicker<'_, '_> {
+    /// Returns the unit closest to `world_pos` among those whose radius covers it,
+    /// otherwise the building occupying the tile at `world_pos`.
+    pub fn entity_at(&self, world_pos: Vec2) -> Option<Entity> {
+        self.units
+            .iter()
+            .filter_map(|(entity, unit, transform)| {
+                let radius = self.unit_registry.units.get(&unit.id)?.radius * FIELD_SIZE;
+                let distance = transform.translation.truncate().distance(world_pos);
+                (distance <= radius).then_some((entity, distance))
+            })
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(entity, _)| entity)
+            .or_else(|| self.map.occupant_at(Map::world_to_global(world_pos)))
+    }
+}
+
+/// Command waiting for the player to click its target.
+/// Only set while in [`InputMode::Target`].
+#[derive(Resource, Default)]
+struct PendingCommand {
+    command: Option<CommandEntry>,
+    issuers: Vec<Entity>,
+}
+
+/// Everything needed to issue commands, e.g. from the control panel.
+#[derive(SystemParam)]
+pub struct CommandIssuer<'w, 's> {
+    commands: Commands<'w, 's>,
+    registry: Res<'w, CommandRegistry>,
+    dispatcher_pipeline: Res<'w, CommandDispatcherPipeline>,
+    cursor: Res<'w, MouseCursor>,
+    pending: ResMut<'w, PendingCommand>,
+    next_mode: ResMut<'w, NextState<InputMode>>,
+}
+
+impl CommandIssuer<'_, '_> {
+    /// Issues the command to the entities according to its [`CommandInputMode`].
+    /// Commands that need a selected target enter [`InputMode::Target`] until the player
+    /// clicked one.
+    pub fn issue(&mut self, command_type: &str, issuers: Vec<Entity>) {
+        let Some(entry) = self.registry.get(command_type) else {
+            warn!("Cannot issue unknown command '{}'", command_type);
+            return;
+        };
+        match entry.input_mode {
+            CommandInputMode::Immediate => {
+                self.dispatch(command_type, CommandPayload::None, issuers);
+            }
+            CommandInputMode::ImmediateSpatial => {
+                if let Some(world_pos) = self.cursor.world_position() {
+                    self.dispatch(
+                        command_type,
+                        CommandPayload::TargetPoint(world_pos),
+                        issuers,
+                    );
+                }
+            }
+            CommandInputMode::SelectTargetedPoint
+            | CommandInputMode::SelectTargetedEntity
+            | CommandInputMode::SelectTargetedPointOrEntity => {
+                self.pending.command = Some(entry.clone());
+                self.pending.issuers = issuers;
+                self.next_mode.set(InputMode::Target);
+            }
+        }
+    }
+
+    pub fn dispatch(&mut self, command_type: &str, payload: CommandPayload, issuers: Vec<Entity>) {
+        self.dispatcher_pipeline.dispatch(
+            CommandEvent {
+                command_type: command_type.to_string(),
+                payload,
+                issuers,
+            },
+            &mut self.commands,
+        );
+    }
+}
+
+fn exit_target_mode(mut pending: ResMut<PendingCommand>) {
+    *pending = PendingCommand::default();
+}
+
+/// Left click selects the target of the [`PendingCommand`] and dispatches it,
+/// escape or right click cancel it.
+fn target_mode_controls(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    picker: EntityPicker,
+    mut issuer: CommandIssuer,
+) {
+    if keyboard_input.just_pressed(key_bindings::CANCEL)
+        || mouse_input.just_pressed(MouseButton::Right)
+    {
+        issuer.next_mode.set(InputMode::Normal);
+        return;
+    }
+    if !mouse_input.just_pressed(MouseButton::Left) {
+        return;
+    }
+    let Some(command) = issuer.pending.command.clone() else {
+        issuer.next_mode.set(InputMode::Normal);
+        return;
+    };
+    let Some(world_pos) = issuer.cursor.world_position() else {
+        return;
+    };
+    let payload = match command.input_mode {
+        CommandInputMode::SelectTargetedEntity => match picker.entity_at(world_pos) {
+            Some(entity) => CommandPayload::TargetEntity(entity),
+            // keep waiting for a click on an entity
+            None => return,
+        },
+        CommandInputMode::SelectTargetedPointOrEntity => picker.entity_at(world_pos).map_or(
+            CommandPayload::TargetPoint(world_pos),
+            CommandPayload::TargetEntity,
+        ),
+        _ => CommandPayload::TargetPoint(world_pos),
+    };
+    let issuers = std::mem::take(&mut issuer.pending.issuers);
+    issuer.dispatch(&command.command_type, payload, issuers);
+    issuer.next_mode.set(InputMode::Normal);
+}
+
 fn control_panel_system(
     query: Query<
         (&Interaction, &ControlPanelSlot, &mut BackgroundColor),
         (Changed<Interaction>, With<Button>),
     >,
+    mut state: ResMut<ControlPanelState>,
+    registry: Res<ControlPanelRegistry>,
+    mut issuer: CommandIssuer,
+    mut enter_build_mode: MessageWriter<EnterBuildMode>,
 ) {
     for (interaction, slot, mut background_color) in query {
         match *interaction {
             Interaction::Pressed => {
                 *background_color = CONTROL_PANEL_SLOT_COLOR_ACTIVE.into();
-                info!(
-                    "Control panel slot pressed: row {}, column {}",
-                    slot.row, slot.column
-                );
+                let Some(action) = state.current_panel(&registry).and_then(|panel| {
+                    panel.entries[slot.row as usize][slot.column as usize].clone()
+                }) else {
+                    continue;
+                };
+                let (command_id, transition) = match &action {
+                    ControlPanelAction::ExecuteCommand(command_id) => (Some(command_id), None),
+                    ControlPanelAction::TransitionPanel(transition) => (None, Some(transition)),
+                    ControlPanelAction::ExecuteAndTransition {
+                        command_id,
+                        transition,
+                    } => (Some(command_id), Some(transition)),
+                    ControlPanelAction::EnterBuildMode(building_id) => {
+                        enter_build_mode.write(EnterBuildMode {
+                            building_id: building_id.clone(),
+                        });
+                        (None, None)
+                    }
+                };
+                if let Some(command_id) = command_id {
+                    issuer.issue(command_id, state.entities.clone());
+                }
+                if let Some(transition) = transition {
+                    state.transition(transition, &registry);
+                }
             }
             Interaction::Hovered => {
                 *background_color = CONTROL_PANEL_SLOT_COLOR_HOVER.into();
@@ -371,6 +678,63 @@ fn control_panel_system(
     }
 }
 
+/// Parses the name of a [`KeyCode`] as written in definition files, e.g. `"KeyB"` or `"F1"`.
+/// Only letters, digits and function keys are supported.
+pub fn parse_key_code(name: &str) -> Option<KeyCode> {
+    let key = match name {
+        "KeyA" => KeyCode::KeyA,
+        "KeyB" => KeyCode::KeyB,
+        "KeyC" => KeyCode::KeyC,
+        "KeyD" => KeyCode::KeyD,
+        "KeyE" => KeyCode::KeyE,
+        "KeyF" => KeyCode::KeyF,
+        "KeyG" => KeyCode::KeyG,
+        "KeyH" => KeyCode::KeyH,
+        "KeyI" => KeyCode::KeyI,
+        "KeyJ" => KeyCode::KeyJ,
+        "KeyK" => KeyCode::KeyK,
+        "KeyL" => KeyCode::KeyL,
+        "KeyM" => KeyCode::KeyM,
+        "KeyN" => KeyCode::KeyN,
+        "KeyO" => KeyCode::KeyO,
+        "KeyP" => KeyCode::KeyP,
+        "KeyQ" => KeyCode::KeyQ,
+        "KeyR" => KeyCode::KeyR,
+        "KeyS" => KeyCode::KeyS,
+        "KeyT" => KeyCode::KeyT,
+        "KeyU" => KeyCode::KeyU,
+        "KeyV" => KeyCode::KeyV,
+        "KeyW" => KeyCode::KeyW,
+        "KeyX" => KeyCode::KeyX,
+        "KeyY" => KeyCode::KeyY,
+        "KeyZ" => KeyCode::KeyZ,
+        "Digit0" => KeyCode::Digit0,
+        "Digit1" => KeyCode::Digit1,
+        "Digit2" => KeyCode::Digit2,
+        "Digit3" => KeyCode::Digit3,
+        "Digit4" => KeyCode::Digit4,
+        "Digit5" => KeyCode::Digit5,
+        "Digit6" => KeyCode::Digit6,
+        "Digit7" => KeyCode::Digit7,
+        "Digit8" => KeyCode::Digit8,
+        "Digit9" => KeyCode::Digit9,
+        "F1" => KeyCode::F1,
+        "F2" => KeyCode::F2,
+        "F3" => KeyCode::F3,
+        "F4" => KeyCode::F4,
+        "F5" => KeyCode::F5,
+        "F6" => KeyCode::F6,
+        "F7" => KeyCode::F7,
+        "F8" => KeyCode::F8,
+        "F9" => KeyCode::F9,
+        "F10" => KeyCode::F10,
+        "F11" => KeyCode::F11,
+        "F12" => KeyCode::F12,
+        _ => return None,
+    };
+    Some(key)
+}
+
 pub struct UserControlsPlugin;
 
 impl Plugin for UserControlsPlugin {
@@ -378,7 +742,27 @@ impl Plugin for UserControlsPlugin {
         app.init_resource::<CommandRegistry>()
             .init_resource::<ControlPanelRegistry>()
             .init_resource::<CommandDispatcherPipeline>()
+            .init_resource::<ControlPanelState>()
+            .init_resource::<PendingCommand>()
+            .init_asset::<CommandDefinitions>()
+            .init_asset::<ControlPanelDefinitions>()
+            .register_asset_loader(RonAssetLoader::<CommandDefinitions>::new(&["commands.ron"]))
+            .register_asset_loader(RonAssetLoader::<ControlPanelDefinitions>::new(&[
+                "panels.ron",
+            ]))
             .add_systems(Startup, setup_ui)
-            .add_systems(Update, control_panel_system);
+            .add_systems(OnExit(InputMode::Target), exit_target_mode)
+            .add_systems(
+                Update,
+                (
+                    target_mode_controls
+                        .run_if(in_state(InputMode::Target).and(not(control_panel_hovered))),
+                    show_selection_in_control_panel,
+                    control_panel_system,
+                    update_control_panel_labels,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::Game)),
+            );
     }
 }